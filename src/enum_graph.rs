@@ -0,0 +1,4034 @@
+//! The enum-based computation graph from the post, pulled out into a module so that it can grow
+//! beyond what fits in a single doc test.
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::mem;
+use std::ops::{Add, Index, Mul};
+use std::str;
+
+use self::metadata::Metadata;
+
+mod bindings;
+mod canonical;
+mod context;
+mod custom;
+mod diff;
+mod dual;
+mod evaluator;
+mod export;
+mod fixed;
+mod infix;
+mod interval;
+mod memo;
+mod metadata;
+mod polynomial;
+mod program;
+mod random;
+mod rational;
+mod reduce;
+mod rewrite;
+mod scalar;
+mod spec;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod trace;
+mod transform;
+mod typed;
+mod vector;
+mod weights;
+
+pub use self::bindings::{Bindings, BindingsBuilder};
+pub use self::context::Context;
+pub use self::custom::{Closure, CustomEval, CustomGrad};
+pub use self::diff::GraphDelta;
+pub use self::dual::Dual;
+pub use self::evaluator::Evaluator;
+pub use self::export::OnnxOp;
+pub use self::fixed::Fixed;
+pub use self::memo::MemoCache;
+pub use self::program::{Instruction, Program};
+pub use self::rational::Rational;
+pub use self::reduce::ReduceOp;
+pub use self::rewrite::Rule;
+pub use self::scalar::Scalar;
+pub use self::spec::{NodeSpec, SpecError};
+pub use self::trace::TraceEntry;
+pub use self::typed::{ConstantKind, TypedIdx, VariableKind};
+
+/// To enable this to be used in HashMap and HashSet, this derives Eq, PartialEq, and Hash
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Idx(pub(crate) usize);
+
+impl Add for Idx {
+    type Output = Node;
+
+    fn add(self, rhs: Idx) -> Node {
+        Node::Sum {
+            children: vec![self, rhs],
+        }
+    }
+}
+
+impl Mul for Idx {
+    type Output = Node;
+
+    fn mul(self, rhs: Idx) -> Node {
+        Node::Product {
+            children: vec![self, rhs],
+        }
+    }
+}
+
+impl Mul<f64> for Idx {
+    type Output = Node;
+
+    fn mul(self, factor: f64) -> Node {
+        Node::Scale {
+            child: self,
+            factor,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Node {
+    Constant(f64),
+    /// A fixed-length vector of values. This only works with `Graph::evaluate_vectors`; the
+    /// scalar evaluators panic if they reach one.
+    VectorConstant(Vec<f64>),
+    Variable,
+    /// A value supplied at evaluation time by `Graph::evaluate_with_externals`, looked up by
+    /// `id`. Unlike a variable, its derivative is zero unless it's explicitly included in `wrt`.
+    External {
+        id: usize,
+    },
+    Sum {
+        children: Vec<Idx>,
+    },
+    /// The product of the children. Its derivative shares running products between the terms
+    /// of the product rule, so it only takes O(n) nodes.
+    ///
+    /// ```
+    /// use std::collections::{HashMap, HashSet};
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let xs: Vec<_> = (0..4).map(|_| g.push(Node::Variable)).collect();
+    /// let product = g.push(Node::Product { children: xs.clone() });
+    ///
+    /// let x_values = [2.0, 3.0, 5.0, 7.0];
+    /// let values: HashMap<_, _> = xs.iter().cloned().zip(x_values.iter().cloned()).collect();
+    ///
+    /// // With respect to every x, the naive product rule gives the sum of the products of all
+    /// // but one x
+    /// let naive: f64 = (0..4)
+    ///     .map(|i| (0..4).filter(|&j| j != i).map(|j| x_values[j]).product::<f64>())
+    ///     .sum();
+    /// let wrt: HashSet<_> = xs.iter().cloned().collect();
+    /// let (derivative, subgraph) = g.derivative(product, wrt).unwrap();
+    /// assert_eq!(naive, g.evaluate_subgraph(subgraph, values.clone())[&derivative]);
+    ///
+    /// // And with respect to each x on its own
+    /// let gradients = g.gradient_vector(product, &values).unwrap();
+    /// for (i, &x) in xs.iter().enumerate() {
+    ///     let wrt: HashSet<_> = vec![x].into_iter().collect();
+    ///     let (derivative, subgraph) = g.derivative(product, wrt).unwrap();
+    ///     let expected = (0..4).filter(|&j| j != i).map(|j| x_values[j]).product::<f64>();
+    ///     assert_eq!(expected, g.evaluate_subgraph(subgraph, values.clone())[&derivative]);
+    ///     assert_eq!((x, expected), gradients[i]);
+    /// }
+    /// ```
+    Product {
+        children: Vec<Idx>,
+    },
+    Exp {
+        child: Idx,
+    },
+    /// The natural logarithm. Its derivative `1 / x` blows up near zero, see
+    /// `Graph::gradients_with_eps`.
+    Ln {
+        child: Idx,
+    },
+    /// `ln(sum(exp(x_i)))`, computed stably by subtracting the largest input before
+    /// exponentiating. This is the building block for softmax and cross-entropy.
+    ///
+    /// ```
+    /// use std::collections::{HashMap, HashSet};
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Variable);
+    /// let lse = g.push(Node::LogSumExp { children: vec![a, b] });
+    ///
+    /// let zeros: HashMap<_, _> = vec![(a, 0.0), (b, 0.0)].into_iter().collect();
+    /// assert_eq!(2f64.ln(), g.evaluate(zeros)[&lse]);
+    ///
+    /// // Large inputs don't overflow
+    /// let large: HashMap<_, _> = vec![(a, 1000.0), (b, 1000.0)].into_iter().collect();
+    /// assert_eq!(1000.0 + 2f64.ln(), g.evaluate(large)[&lse]);
+    ///
+    /// // The gradient is the softmax of the inputs
+    /// let values: HashMap<_, _> = vec![(a, 1.0), (b, 2.0)].into_iter().collect();
+    /// let softmax_a = 1f64.exp() / (1f64.exp() + 2f64.exp());
+    /// for (wrt, expected) in vec![(a, softmax_a), (b, 1.0 - softmax_a)] {
+    ///     let wrt: HashSet<_> = vec![wrt].into_iter().collect();
+    ///     let (d_lse, subgraph) = g.derivative(lse, wrt).unwrap();
+    ///     let gradient = g.evaluate_subgraph(subgraph, values.clone())[&d_lse];
+    ///     assert!((expected - gradient).abs() < 1e-12);
+    /// }
+    /// ```
+    LogSumExp {
+        children: Vec<Idx>,
+    },
+    /// The sum of the squares of the children, which is the usual L2 penalty.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Variable);
+    /// let norm = g.push(Node::SquaredNorm { children: vec![a, b] });
+    ///
+    /// let values: HashMap<_, _> = vec![(a, 3.0), (b, 4.0)].into_iter().collect();
+    /// assert_eq!(25.0, g.evaluate(values.clone())[&norm]);
+    /// assert_eq!(vec![(a, 6.0), (b, 8.0)], g.gradient_vector(norm, &values).unwrap());
+    /// ```
+    SquaredNorm {
+        children: Vec<Idx>,
+    },
+    Tanh {
+        child: Idx,
+    },
+    /// `ln(1 + exp(x))`, computed as `max(x, 0) + ln(1 + exp(-|x|))` so that large inputs don't
+    /// overflow. Its derivative is the sigmoid of the child.
+    ///
+    /// ```
+    /// use std::collections::{HashMap, HashSet};
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let x = g.push(Node::Variable);
+    /// let softplus = g.push(Node::Softplus { child: x });
+    /// let at = |x_value: f64| -> HashMap<_, _> { vec![(x, x_value)].into_iter().collect() };
+    ///
+    /// assert_eq!(2f64.ln(), g.evaluate(at(0.0))[&softplus]);
+    /// assert_eq!(1000.0, g.evaluate(at(1000.0))[&softplus]);
+    ///
+    /// let wrt: HashSet<_> = vec![x].into_iter().collect();
+    /// let (d_softplus, subgraph) = g.derivative(softplus, wrt).unwrap();
+    /// assert_eq!(0.5, g.evaluate_subgraph(subgraph, at(0.0))[&d_softplus]);
+    /// assert_eq!(vec![(x, 0.5)], g.gradient_vector(softplus, &at(0.0)).unwrap());
+    /// ```
+    Softplus {
+        child: Idx,
+    },
+    /// The hyperbolic sine. Its derivative is `cosh(x)`.
+    ///
+    /// ```
+    /// use std::collections::{HashMap, HashSet};
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let x = g.push(Node::Variable);
+    /// let sinh = g.push(Node::Sinh { child: x });
+    /// let values: HashMap<_, _> = vec![(x, 1.0)].into_iter().collect();
+    /// assert_eq!(1f64.sinh(), g.evaluate(values.clone())[&sinh]);
+    ///
+    /// let wrt: HashSet<_> = vec![x].into_iter().collect();
+    /// let (derivative, subgraph) = g.derivative(sinh, wrt).unwrap();
+    /// let expected = 1f64.cosh();
+    /// assert!((expected - g.evaluate_subgraph(subgraph, values)[&derivative]).abs() < 1e-12);
+    /// ```
+    Sinh {
+        child: Idx,
+    },
+    /// The hyperbolic cosine. Its derivative is `sinh(x)`.
+    ///
+    /// ```
+    /// use std::collections::{HashMap, HashSet};
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let x = g.push(Node::Variable);
+    /// let cosh = g.push(Node::Cosh { child: x });
+    /// let values: HashMap<_, _> = vec![(x, 1.0)].into_iter().collect();
+    /// assert_eq!(1f64.cosh(), g.evaluate(values.clone())[&cosh]);
+    ///
+    /// let wrt: HashSet<_> = vec![x].into_iter().collect();
+    /// let (derivative, subgraph) = g.derivative(cosh, wrt).unwrap();
+    /// let expected = 1f64.sinh();
+    /// assert!((expected - g.evaluate_subgraph(subgraph, values)[&derivative]).abs() < 1e-12);
+    /// ```
+    Cosh {
+        child: Idx,
+    },
+    /// The inverse tangent. Its derivative is `1 / (1 + x^2)`.
+    ///
+    /// ```
+    /// use std::collections::{HashMap, HashSet};
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let x = g.push(Node::Variable);
+    /// let atan = g.push(Node::Atan { child: x });
+    /// let values: HashMap<_, _> = vec![(x, 1.0)].into_iter().collect();
+    /// assert_eq!(std::f64::consts::FRAC_PI_4, g.evaluate(values.clone())[&atan]);
+    ///
+    /// let wrt: HashSet<_> = vec![x].into_iter().collect();
+    /// let (derivative, subgraph) = g.derivative(atan, wrt).unwrap();
+    /// let expected = 0.5;
+    /// assert!((expected - g.evaluate_subgraph(subgraph, values)[&derivative]).abs() < 1e-12);
+    /// ```
+    Atan {
+        child: Idx,
+    },
+    /// The inverse sine, which is only defined between -1 and 1. Its derivative is
+    /// `1 / sqrt(1 - x^2)`.
+    ///
+    /// ```
+    /// use std::collections::{HashMap, HashSet};
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let x = g.push(Node::Variable);
+    /// let asin = g.push(Node::Asin { child: x });
+    /// let values: HashMap<_, _> = vec![(x, 0.5)].into_iter().collect();
+    /// assert_eq!(std::f64::consts::FRAC_PI_6, g.evaluate(values.clone())[&asin]);
+    ///
+    /// let wrt: HashSet<_> = vec![x].into_iter().collect();
+    /// let (derivative, subgraph) = g.derivative(asin, wrt).unwrap();
+    /// let expected = 1.0 / 0.75f64.sqrt();
+    /// assert!((expected - g.evaluate_subgraph(subgraph, values)[&derivative]).abs() < 1e-12);
+    /// ```
+    Asin {
+        child: Idx,
+    },
+    /// Raises the child to a constant power.
+    Pow {
+        child: Idx,
+        exponent: f64,
+    },
+    /// Raises `base` to the power of `exponent`, where both are nodes. The derivative is
+    /// `base^exponent * (exponent' * ln(base) + exponent * base' / base)`.
+    ///
+    /// A base that isn't positive only has a real power for some exponents, so there the
+    /// partial derivative with respect to the exponent is taken to be zero. This keeps e.g.
+    /// `(-2)^3` differentiable with respect to its base.
+    ///
+    /// ```
+    /// use std::collections::{HashMap, HashSet};
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let x = g.push(Node::Variable);
+    /// let y = g.push(Node::Variable);
+    /// let pow = g.push(Node::PowNode { base: x, exponent: y });
+    /// let at = |x_value: f64, y_value: f64| -> HashMap<_, _> {
+    ///     vec![(x, x_value), (y, y_value)].into_iter().collect()
+    /// };
+    ///
+    /// assert_eq!(8.0, g.evaluate(at(2.0, 3.0))[&pow]);
+    /// let ln_2 = 2.0f64.ln();
+    /// assert_eq!(vec![(x, 12.0), (y, 8.0 * ln_2)], g.gradient_vector(pow, &at(2.0, 3.0)).unwrap());
+    ///
+    /// assert_eq!(-8.0, g.evaluate(at(-2.0, 3.0))[&pow]);
+    /// assert_eq!(vec![(x, 12.0), (y, 0.0)], g.gradient_vector(pow, &at(-2.0, 3.0)).unwrap());
+    ///
+    /// // The symbolic derivative agrees
+    /// for &(wrt, expected) in &[(x, 12.0), (y, 8.0 * ln_2)] {
+    ///     let (derivative, subgraph) = g.derivative(pow, vec![wrt].into_iter().collect()).unwrap();
+    ///     let result = g.evaluate_subgraph(subgraph, at(2.0, 3.0));
+    ///     assert!((expected - result[&derivative]).abs() < 1e-12);
+    /// }
+    /// let (derivative, subgraph) = g.derivative(pow, vec![y].into_iter().collect()).unwrap();
+    /// assert_eq!(0.0, g.evaluate_subgraph(subgraph, at(-2.0, 3.0))[&derivative]);
+    /// ```
+    PowNode {
+        base: Idx,
+        exponent: Idx,
+    },
+    /// The Gaussian Error Linear Unit, using the usual tanh approximation:
+    /// `0.5 * x * (1 + tanh(sqrt(2 / pi) * (x + 0.044715 * x^3)))`. Its derivative is taken by
+    /// expanding it into `Tanh`, `Pow`, `Product`, and `Sum` nodes, see `Graph::push_gelu`.
+    ///
+    /// ```
+    /// use std::collections::{HashMap, HashSet};
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let x = g.push(Node::Variable);
+    /// let gelu = g.push(Node::Gelu { child: x });
+    /// let at = |x_value: f64| -> HashMap<_, _> { vec![(x, x_value)].into_iter().collect() };
+    ///
+    /// assert_eq!(0.0, g.evaluate(at(0.0))[&gelu]);
+    ///
+    /// // Increasing for positive inputs
+    /// let outputs: Vec<f64> = (1..10).map(|i| g.evaluate(at(i as f64 * 0.5))[&gelu]).collect();
+    /// assert!(outputs.windows(2).all(|pair| pair[0] < pair[1]));
+    ///
+    /// // The derivative of the expansion matches the numerical derivative
+    /// let wrt: HashSet<_> = vec![x].into_iter().collect();
+    /// let (d_gelu, subgraph) = g.derivative(gelu, wrt).unwrap();
+    /// let derivative = g.evaluate_subgraph(subgraph, at(0.7))[&d_gelu];
+    /// assert!((g.numerical_gradient(gelu, x, &at(0.7)) - derivative).abs() < 1e-6);
+    ///
+    /// // So does reverse mode
+    /// let (_, gradients) = g.forward_backward(gelu, at(0.7)).unwrap();
+    /// assert!((gradients[&x] - derivative).abs() < 1e-9);
+    /// ```
+    Gelu {
+        child: Idx,
+    },
+    /// The largest of the children. The derivative follows whichever child is largest, with ties
+    /// going to the first one.
+    ///
+    /// ```
+    /// use std::collections::{HashMap, HashSet};
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Variable);
+    /// let max = g.push(Node::Max { children: vec![a, b] });
+    ///
+    /// let values: HashMap<_, _> = vec![(a, 1.0), (b, 2.0)].into_iter().collect();
+    /// let wrt: HashSet<_> = vec![b].into_iter().collect();
+    /// let (d_max, subgraph) = g.derivative(max, wrt).unwrap();
+    /// assert_eq!(1.0, g.evaluate_subgraph(subgraph, values.clone())[&d_max]);
+    /// assert_eq!(vec![(a, 0.0), (b, 1.0)], g.gradient_vector(max, &values).unwrap());
+    /// ```
+    Max {
+        children: Vec<Idx>,
+    },
+    /// Takes the value of `if_true` when `condition` is greater than zero, and `if_false`
+    /// otherwise.
+    ///
+    /// The whole gradient goes to the branch that was taken. The condition is a hard threshold,
+    /// so its gradient is zero everywhere, including at zero itself, where the true derivative
+    /// doesn't exist. At that boundary the false branch is taken, so that's also where the
+    /// gradient goes.
+    ///
+    /// ```
+    /// use std::collections::{HashMap, HashSet};
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let condition = g.push(Node::Variable);
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Variable);
+    /// let select = g.push(Node::Select { condition, if_true: a, if_false: b });
+    /// let at = |condition_value: f64| -> HashMap<_, _> {
+    ///     vec![(condition, condition_value), (a, 2.0), (b, 3.0)].into_iter().collect()
+    /// };
+    ///
+    /// assert_eq!(
+    ///     vec![(condition, 0.0), (a, 1.0), (b, 0.0)],
+    ///     g.gradient_vector(select, &at(0.5)).unwrap()
+    /// );
+    /// assert_eq!(
+    ///     vec![(condition, 0.0), (a, 0.0), (b, 1.0)],
+    ///     g.gradient_vector(select, &at(0.0)).unwrap()
+    /// );
+    ///
+    /// let wrt: HashSet<_> = vec![condition].into_iter().collect();
+    /// let (d_select, subgraph) = g.derivative(select, wrt).unwrap();
+    /// assert_eq!(0.0, g.evaluate_subgraph(subgraph, at(0.5))[&d_select]);
+    /// ```
+    Select {
+        condition: Idx,
+        if_true: Idx,
+        if_false: Idx,
+    },
+    /// `a.rem_euclid(b)`, so the result is always between zero and `|b|`, even when `a` or `b`
+    /// is negative. The derivative is taken to be 1 with respect to `a` and 0 with respect to
+    /// `b`. That's right almost everywhere for `a`, but it ignores the jumps whenever `a` is a
+    /// multiple of `b`, and on the `b` side it's only right when `a / b` rounds down to zero.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Variable);
+    /// let modulo = g.push(Node::Modulo { a, b });
+    ///
+    /// let values: HashMap<_, _> = vec![(a, 7.0), (b, 3.0)].into_iter().collect();
+    /// assert_eq!(1.0, g.evaluate(values.clone())[&modulo]);
+    /// assert_eq!(vec![(a, 1.0), (b, 0.0)], g.gradient_vector(modulo, &values).unwrap());
+    ///
+    /// let values: HashMap<_, _> = vec![(a, -7.0), (b, 3.0)].into_iter().collect();
+    /// assert_eq!(2.0, g.evaluate(values)[&modulo]);
+    /// ```
+    Modulo {
+        a: Idx,
+        b: Idx,
+    },
+    /// `numerator / denominator`, or `default` when the denominator is zero, so that this never
+    /// produces an infinity or NaN by dividing by zero. The derivative follows the quotient
+    /// rule, except that it's zero wherever `default` is used.
+    ///
+    /// ```
+    /// use std::collections::{HashMap, HashSet};
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let n = g.push(Node::Variable);
+    /// let d = g.push(Node::Variable);
+    /// let div = g.push(Node::SafeDiv { numerator: n, denominator: d, default: -1.0 });
+    /// let at = |n_value, d_value| -> HashMap<_, _> {
+    ///     vec![(n, n_value), (d, d_value)].into_iter().collect()
+    /// };
+    ///
+    /// assert_eq!(1.5, g.evaluate(at(3.0, 2.0))[&div]);
+    /// assert_eq!(vec![(n, 0.5), (d, -0.75)], g.gradient_vector(div, &at(3.0, 2.0)).unwrap());
+    /// let wrt: HashSet<_> = vec![d].into_iter().collect();
+    /// let (d_div, subgraph) = g.derivative(div, wrt).unwrap();
+    /// assert_eq!(-0.75, g.evaluate_subgraph(subgraph.clone(), at(3.0, 2.0))[&d_div]);
+    ///
+    /// assert_eq!(-1.0, g.evaluate(at(3.0, 0.0))[&div]);
+    /// assert_eq!(vec![(n, 0.0), (d, 0.0)], g.gradient_vector(div, &at(3.0, 0.0)).unwrap());
+    /// assert_eq!(0.0, g.evaluate_subgraph(subgraph, at(3.0, 0.0))[&d_div]);
+    /// ```
+    SafeDiv {
+        numerator: Idx,
+        denominator: Idx,
+        default: f64,
+    },
+    /// `1` if `a` is greater than `b`, and `0` otherwise, e.g. as the condition of a `Select`.
+    /// Its derivative is zero.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Variable);
+    /// let greater = g.push(Node::Greater { a, b });
+    /// let at = |a_value, b_value| -> HashMap<_, _> {
+    ///     vec![(a, a_value), (b, b_value)].into_iter().collect()
+    /// };
+    ///
+    /// assert_eq!(1.0, g.evaluate(at(2.0, 1.0))[&greater]);
+    /// assert_eq!(0.0, g.evaluate(at(1.0, 1.0))[&greater]);
+    /// assert_eq!(0.0, g.evaluate(at(1.0, 2.0))[&greater]);
+    /// assert_eq!(vec![(a, 0.0), (b, 0.0)], g.gradient_vector(greater, &at(2.0, 1.0)).unwrap());
+    /// ```
+    Greater {
+        a: Idx,
+        b: Idx,
+    },
+    /// `1` if `a` and `b` are exactly equal, and `0` otherwise. Its derivative is zero.
+    ///
+    /// This is floating-point equality, so values that differ only by rounding error aren't
+    /// equal, e.g. `0.1 + 0.2` and `0.3`. NaN isn't equal to anything, including itself.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Variable);
+    /// let equal = g.push(Node::Equal { a, b });
+    /// let at = |a_value, b_value| -> HashMap<_, _> {
+    ///     vec![(a, a_value), (b, b_value)].into_iter().collect()
+    /// };
+    ///
+    /// assert_eq!(1.0, g.evaluate(at(0.5, 0.5))[&equal]);
+    /// assert_eq!(0.0, g.evaluate(at(0.1 + 0.2, 0.3))[&equal]);
+    /// assert_eq!(vec![(a, 0.0), (b, 0.0)], g.gradient_vector(equal, &at(0.5, 0.5)).unwrap());
+    /// ```
+    Equal {
+        a: Idx,
+        b: Idx,
+    },
+    /// The index of the bucket that the child falls into, as a number: the count of `edges`
+    /// that are less than or equal to it. The edges must be sorted, which
+    /// `Graph::push_bucketize` checks. This is a step function, so its derivative is zero.
+    Bucketize {
+        child: Idx,
+        edges: Vec<f64>,
+    },
+    /// Looks the child up in a table by interpolating linearly between the points `(xs, ys)`,
+    /// and holding the first or last `y` outside of the table. The derivative is the slope of
+    /// the segment that the child falls in, using the segment to the right at a point, which is
+    /// itself a `Lerp` over a table of slopes. `xs` must be sorted and as long as `ys`, which
+    /// `Graph::push_lerp` checks.
+    ///
+    /// ```
+    /// use std::collections::{HashMap, HashSet};
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let x = g.push(Node::Variable);
+    /// let lerp = g.push_lerp(x, vec![0.0, 1.0, 3.0], vec![0.0, 2.0, 1.0]);
+    /// let at = |x_value: f64| -> HashMap<_, _> { vec![(x, x_value)].into_iter().collect() };
+    ///
+    /// assert_eq!(1.5, g.evaluate(at(2.0))[&lerp]);
+    /// assert_eq!(vec![(x, -0.5)], g.gradient_vector(lerp, &at(2.0)).unwrap());
+    /// assert_eq!(1.0, g.evaluate(at(0.5))[&lerp]);
+    /// assert_eq!(vec![(x, 2.0)], g.gradient_vector(lerp, &at(0.5)).unwrap());
+    /// assert_eq!(1.0, g.evaluate(at(10.0))[&lerp]);
+    /// assert_eq!(vec![(x, 0.0)], g.gradient_vector(lerp, &at(10.0)).unwrap());
+    ///
+    /// let wrt: HashSet<_> = vec![x].into_iter().collect();
+    /// let (derivative, subgraph) = g.derivative(lerp, wrt).unwrap();
+    /// for &(x_value, slope) in &[(-1.0, 0.0), (0.5, 2.0), (1.0, -0.5), (2.0, -0.5), (3.0, 0.0)] {
+    ///     assert_eq!(slope, g.evaluate_subgraph(subgraph.clone(), at(x_value))[&derivative]);
+    /// }
+    ///
+    /// // The derivative is made of built-in nodes, so it can be written out and read back
+    /// let text = g.to_edge_list();
+    /// assert_eq!(g, Graph::from_edge_list(&text).unwrap());
+    /// ```
+    Lerp {
+        child: Idx,
+        xs: Vec<f64>,
+        ys: Vec<f64>,
+    },
+    /// `-1`, `0`, or `1` depending on the sign of the child, for building piecewise functions.
+    /// This is a step function, so its derivative is zero everywhere, even at zero where it's
+    /// really undefined.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let x = g.push(Node::Variable);
+    /// let sign = g.push(Node::Sign { child: x });
+    ///
+    /// for &(input, expected) in &[(-2.5, -1.0), (0.0, 0.0), (3.0, 1.0)] {
+    ///     let values: HashMap<_, _> = vec![(x, input)].into_iter().collect();
+    ///     assert_eq!(expected, g.evaluate(values.clone())[&sign]);
+    ///     assert_eq!(vec![(x, 0.0)], g.gradient_vector(sign, &values).unwrap());
+    /// }
+    /// ```
+    Sign {
+        child: Idx,
+    },
+    /// Scales a vector to unit length, `x / ||x||`, in `Graph::evaluate_vectors`. The zero
+    /// vector stays all zeros.
+    ///
+    /// Unlike the elementwise nodes, this has no scalar meaning: each output element depends on
+    /// the whole vector, through the Jacobian `(I - x x^T / ||x||^2) / ||x||`. So, like
+    /// `VectorConstant`, evaluating it anywhere other than `evaluate_vectors` panics, and so
+    /// does differentiating it anywhere other than `Graph::vector_gradients`.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let x = g.push(Node::Variable);
+    /// let unit = g.push(Node::Normalize { child: x });
+    ///
+    /// let values: HashMap<_, _> = vec![(x, vec![3.0, 4.0])].into_iter().collect();
+    /// assert_eq!(vec![0.6, 0.8], g.evaluate_vectors(values).unwrap()[&unit]);
+    /// let values: HashMap<_, _> = vec![(x, vec![0.0, 0.0])].into_iter().collect();
+    /// assert_eq!(vec![0.0, 0.0], g.evaluate_vectors(values).unwrap()[&unit]);
+    /// ```
+    ///
+    /// ```should_panic
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let x = g.push(Node::Variable);
+    /// let unit = g.push(Node::Normalize { child: x });
+    ///
+    /// let values: HashMap<_, _> = vec![(x, -2.0)].into_iter().collect();
+    /// // Panics: a scalar can't be normalized
+    /// g.evaluate(values);
+    /// ```
+    Normalize {
+        child: Idx,
+    },
+    /// The matrix product of `a` and `b`, with elements in row-major order. The shapes come
+    /// from the graph rather than the values: each child must be a two-dimensional `Reshape` or
+    /// another `MatMul`, and the columns of `a` must match the rows of `b`, or else
+    /// `Graph::evaluate_vectors` returns `GraphError::LengthMismatch`. Like `Normalize`, this
+    /// only works with matrices, so the scalar methods panic; use `Graph::vector_gradients` for
+    /// the gradients, `dY * Bᵀ` and `Aᵀ * dY`.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, GraphError, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let x = g.push(Node::Variable);
+    /// let y = g.push(Node::Variable);
+    /// let a = g.push(Node::Reshape { child: x, shape: vec![2, 3] });
+    /// let b = g.push(Node::Reshape { child: y, shape: vec![3, 2] });
+    /// let c = g.push(Node::MatMul { a, b });
+    /// assert_eq!(Some((2, 2)), g.matrix_shape(c));
+    ///
+    /// let values: HashMap<_, _> = vec![
+    ///     (x, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]),
+    ///     (y, vec![7.0, 8.0, 9.0, 10.0, 11.0, 12.0]),
+    /// ]
+    /// .into_iter()
+    /// .collect();
+    /// assert_eq!(
+    ///     vec![58.0, 64.0, 139.0, 154.0],
+    ///     g.evaluate_vectors(values.clone()).unwrap()[&c]
+    /// );
+    ///
+    /// // The gradients of the sum of the elements of c. With dY all ones, dY * Bᵀ gives each
+    /// // element of A the sum of a row of B, and Aᵀ * dY gives each element of B the sum of a
+    /// // column of A.
+    /// let gradients = g.vector_gradients(c, values).unwrap();
+    /// assert_eq!(vec![15.0, 19.0, 23.0, 15.0, 19.0, 23.0], gradients[&x]);
+    /// assert_eq!(vec![5.0, 5.0, 7.0, 7.0, 9.0, 9.0], gradients[&y]);
+    ///
+    /// // A 2x3 matrix can't be multiplied by another 2x3 matrix
+    /// let wrong = g.push(Node::MatMul { a, b: a });
+    /// let values: HashMap<_, _> = vec![(x, vec![1.0; 6]), (y, vec![1.0; 6])].into_iter().collect();
+    /// assert_eq!(None, g.matrix_shape(wrong));
+    /// assert_eq!(Err(GraphError::LengthMismatch(wrong)), g.evaluate_vectors(values));
+    /// ```
+    MatMul {
+        a: Idx,
+        b: Idx,
+    },
+    /// An exponentially weighted moving average step: `alpha * input + (1 - alpha) * prev`.
+    ///
+    /// The graph doesn't remember anything between evaluations, so to smooth a stream, make
+    /// `prev` a variable and bind it to the output of the previous `evaluate` call each time.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let input = g.push(Node::Variable);
+    /// let prev = g.push(Node::Variable);
+    /// let ewma = g.push(Node::Ewma { input, prev, alpha: 0.5 });
+    ///
+    /// let mut state = 0.0;
+    /// let mut outputs = vec![];
+    /// for _ in 0..3 {
+    ///     let values: HashMap<_, _> = vec![(input, 10.0), (prev, state)].into_iter().collect();
+    ///     state = g.evaluate(values)[&ewma];
+    ///     outputs.push(state);
+    /// }
+    /// assert_eq!(vec![5.0, 7.5, 8.75], outputs);
+    /// ```
+    Ewma {
+        input: Idx,
+        prev: Idx,
+        alpha: f64,
+    },
+    /// Passes the child's value through unchanged, as a place to label or observe an
+    /// intermediate value. Its derivative is the child's derivative, without any new nodes.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let x = g.push(Node::Variable);
+    /// let square = g.push(x * x);
+    /// let tap = g.push(Node::Identity { child: square });
+    ///
+    /// let values: HashMap<_, _> = vec![(x, 3.0)].into_iter().collect();
+    /// let result = g.evaluate(values.clone());
+    /// assert_eq!(result[&square], result[&tap]);
+    /// assert_eq!(
+    ///     g.gradient_vector(square, &values).unwrap(),
+    ///     g.gradient_vector(tap, &values).unwrap()
+    /// );
+    /// ```
+    Identity {
+        child: Idx,
+    },
+    /// Gives the child's elements the dimensions in `shape`, in row-major order. Values don't
+    /// carry a shape, so this doesn't change them: `Graph::evaluate_vectors` checks that the
+    /// child has as many elements as `shape` holds, and everywhere else this behaves like
+    /// `Identity`. Reshaping the gradient back is a no-op for the same reason.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, GraphError, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let x = g.push(Node::Variable);
+    /// let matrix = g.push(Node::Reshape { child: x, shape: vec![2, 3] });
+    ///
+    /// let elements = vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+    /// let values: HashMap<_, _> = vec![(x, elements.clone())].into_iter().collect();
+    /// assert_eq!(elements, g.evaluate_vectors(values).unwrap()[&matrix]);
+    ///
+    /// let values: HashMap<_, _> = vec![(x, vec![1.0; 4])].into_iter().collect();
+    /// assert_eq!(Err(GraphError::LengthMismatch(matrix)), g.evaluate_vectors(values));
+    ///
+    /// // The gradient has the same shape as the child
+    /// let values: HashMap<_, _> = vec![(x, 2.0)].into_iter().collect();
+    /// assert_eq!(vec![(x, 1.0)], g.gradient_vector(matrix, &values).unwrap());
+    /// ```
+    Reshape {
+        child: Idx,
+        shape: Vec<usize>,
+    },
+    /// The child's value, which `Graph::evaluate_with_memos` reuses whenever the variables that
+    /// the child depends on haven't changed. Everywhere else this behaves like `Identity`.
+    Memo {
+        child: Idx,
+    },
+    /// Multiplies the child by a constant factor. This is lighter than a `Product` with a
+    /// `Constant`.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let x = g.push(Node::Variable);
+    /// let scaled = g.push(x * 2.0);
+    /// assert_eq!(Node::Scale { child: x, factor: 2.0 }, g[scaled]);
+    ///
+    /// let values: HashMap<_, _> = vec![(x, 3.0)].into_iter().collect();
+    /// assert_eq!(6.0, g.evaluate(values.clone())[&scaled]);
+    /// assert_eq!(vec![(x, 2.0)], g.gradient_vector(scaled, &values).unwrap());
+    /// ```
+    Scale {
+        child: Idx,
+        factor: f64,
+    },
+    /// Combines the children with any `ReduceOp`.
+    ///
+    /// ```
+    /// use std::collections::{HashMap, HashSet};
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node, ReduceOp};
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Variable);
+    /// let values: HashMap<_, _> = vec![(a, 2.0), (b, 5.0)].into_iter().collect();
+    ///
+    /// for &(op, value, d_a, d_b) in &[
+    ///     (ReduceOp::Sum, 7.0, 1.0, 1.0),
+    ///     (ReduceOp::Product, 10.0, 5.0, 2.0),
+    ///     (ReduceOp::Max, 5.0, 0.0, 1.0),
+    ///     (ReduceOp::Min, 2.0, 1.0, 0.0),
+    /// ] {
+    ///     let reduced = g.push(Node::Reduce { op, children: vec![a, b] });
+    ///     assert_eq!(value, g.evaluate(values.clone())[&reduced]);
+    ///
+    ///     // Reverse mode
+    ///     assert_eq!(vec![(a, d_a), (b, d_b)], g.gradient_vector(reduced, &values).unwrap());
+    ///
+    ///     // Forward mode
+    ///     for &(wrt, expected) in &[(a, d_a), (b, d_b)] {
+    ///         let wrt: HashSet<_> = vec![wrt].into_iter().collect();
+    ///         let (derivative, subgraph) = g.derivative(reduced, wrt).unwrap();
+    ///         assert_eq!(expected, g.evaluate_subgraph(subgraph, values.clone())[&derivative]);
+    ///     }
+    /// }
+    /// ```
+    Reduce {
+        op: ReduceOp,
+        children: Vec<Idx>,
+    },
+    /// An operation defined by closures, built with `Node::custom`. Reverse mode only needs
+    /// `grad`, but the symbolic derivative turns each partial into another custom node with no
+    /// `grad`, and differentiating one of those returns `GraphError::NotTwiceDifferentiable`.
+    /// Custom nodes can't be written to or read from the text formats.
+    Custom {
+        children: Vec<Idx>,
+        eval: CustomEval,
+        grad: Option<CustomGrad>,
+    },
+}
+
+impl Node {
+    /// The nodes that this node takes as inputs. Leaves have no children.
+    pub fn children(&self) -> Vec<Idx> {
+        match self {
+            Node::Constant(_)
+            | Node::VectorConstant(_)
+            | Node::Variable
+            | Node::External { .. } => vec![],
+            Node::Sum { children }
+            | Node::Product { children }
+            | Node::LogSumExp { children }
+            | Node::SquaredNorm { children }
+            | Node::Max { children }
+            | Node::Reduce { children, .. }
+            | Node::Custom { children, .. } => children.clone(),
+            Node::Exp { child }
+            | Node::Ln { child }
+            | Node::Tanh { child }
+            | Node::Softplus { child }
+            | Node::Sinh { child }
+            | Node::Cosh { child }
+            | Node::Atan { child }
+            | Node::Asin { child }
+            | Node::Pow { child, .. }
+            | Node::Gelu { child }
+            | Node::Identity { child }
+            | Node::Memo { child }
+            | Node::Reshape { child, .. }
+            | Node::Bucketize { child, .. }
+            | Node::Lerp { child, .. }
+            | Node::Sign { child }
+            | Node::Normalize { child }
+            | Node::Scale { child, .. } => vec![*child],
+            Node::Select {
+                condition,
+                if_true,
+                if_false,
+            } => vec![*condition, *if_true, *if_false],
+            Node::Modulo { a, b }
+            | Node::Greater { a, b }
+            | Node::Equal { a, b }
+            | Node::MatMul { a, b } => vec![*a, *b],
+            Node::PowNode { base, exponent } => vec![*base, *exponent],
+            Node::Ewma { input, prev, .. } => vec![*input, *prev],
+            Node::SafeDiv {
+                numerator,
+                denominator,
+                ..
+            } => vec![*numerator, *denominator],
+        }
+    }
+
+    /// A short lowercase name for this kind of node, used by the text formats.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Node::Constant(_) => "constant",
+            Node::VectorConstant(_) => "vector_constant",
+            Node::Variable => "variable",
+            Node::External { .. } => "external",
+            Node::Sum { .. } => "sum",
+            Node::Product { .. } => "product",
+            Node::Exp { .. } => "exp",
+            Node::Ln { .. } => "ln",
+            Node::LogSumExp { .. } => "logsumexp",
+            Node::SquaredNorm { .. } => "squared_norm",
+            Node::Tanh { .. } => "tanh",
+            Node::Softplus { .. } => "softplus",
+            Node::Sinh { .. } => "sinh",
+            Node::Cosh { .. } => "cosh",
+            Node::Atan { .. } => "atan",
+            Node::Asin { .. } => "asin",
+            Node::Pow { .. } => "pow",
+            Node::PowNode { .. } => "pow_node",
+            Node::Gelu { .. } => "gelu",
+            Node::Identity { .. } => "identity",
+            Node::Memo { .. } => "memo",
+            Node::Reshape { .. } => "reshape",
+            Node::Max { .. } => "max",
+            Node::Select { .. } => "select",
+            Node::Modulo { .. } => "modulo",
+            Node::SafeDiv { .. } => "safe_div",
+            Node::Greater { .. } => "greater",
+            Node::Equal { .. } => "equal",
+            Node::Bucketize { .. } => "bucketize",
+            Node::Lerp { .. } => "lerp",
+            Node::Sign { .. } => "sign",
+            Node::Normalize { .. } => "normalize",
+            Node::MatMul { .. } => "matmul",
+            Node::Ewma { .. } => "ewma",
+            Node::Scale { .. } => "scale",
+            Node::Reduce { op, .. } => match op {
+                ReduceOp::Sum => "reduce_sum",
+                ReduceOp::Product => "reduce_product",
+                ReduceOp::Max => "reduce_max",
+                ReduceOp::Min => "reduce_min",
+            },
+            Node::Custom { .. } => "custom",
+        }
+    }
+
+    /// The bytes that this node owns on the heap, not counting the node itself.
+    fn heap_bytes(&self) -> usize {
+        match self {
+            Node::Sum { children }
+            | Node::Product { children }
+            | Node::LogSumExp { children }
+            | Node::SquaredNorm { children }
+            | Node::Max { children }
+            | Node::Reduce { children, .. }
+            | Node::Custom { children, .. } => children.capacity() * mem::size_of::<Idx>(),
+            Node::Bucketize { edges, .. } => edges.capacity() * mem::size_of::<f64>(),
+            Node::Lerp { xs, ys, .. } => (xs.capacity() + ys.capacity()) * mem::size_of::<f64>(),
+            Node::Reshape { shape, .. } => shape.capacity() * mem::size_of::<usize>(),
+            _ => 0,
+        }
+    }
+
+    /// Returns a copy of this node with each child replaced by `f(child)`.
+    pub fn map_children(&self, mut f: impl FnMut(Idx) -> Idx) -> Node {
+        let mut node = self.clone();
+        match node {
+            Node::Constant(_)
+            | Node::VectorConstant(_)
+            | Node::Variable
+            | Node::External { .. } => {}
+            Node::Sum { ref mut children }
+            | Node::Product { ref mut children }
+            | Node::LogSumExp { ref mut children }
+            | Node::SquaredNorm { ref mut children }
+            | Node::Max { ref mut children }
+            | Node::Reduce {
+                ref mut children, ..
+            }
+            | Node::Custom {
+                ref mut children, ..
+            } => {
+                for child in children.iter_mut() {
+                    *child = f(*child);
+                }
+            }
+            Node::Exp { ref mut child }
+            | Node::Ln { ref mut child }
+            | Node::Tanh { ref mut child }
+            | Node::Softplus { ref mut child }
+            | Node::Sinh { ref mut child }
+            | Node::Cosh { ref mut child }
+            | Node::Atan { ref mut child }
+            | Node::Asin { ref mut child }
+            | Node::Pow { ref mut child, .. }
+            | Node::Gelu { ref mut child }
+            | Node::Identity { ref mut child }
+            | Node::Memo { ref mut child }
+            | Node::Reshape { ref mut child, .. }
+            | Node::Bucketize { ref mut child, .. }
+            | Node::Lerp { ref mut child, .. }
+            | Node::Sign { ref mut child }
+            | Node::Normalize { ref mut child }
+            | Node::Scale { ref mut child, .. } => *child = f(*child),
+            Node::Select {
+                ref mut condition,
+                ref mut if_true,
+                ref mut if_false,
+            } => {
+                *condition = f(*condition);
+                *if_true = f(*if_true);
+                *if_false = f(*if_false);
+            }
+            Node::Modulo {
+                ref mut a,
+                ref mut b,
+            }
+            | Node::PowNode {
+                base: ref mut a,
+                exponent: ref mut b,
+            }
+            | Node::Greater {
+                ref mut a,
+                ref mut b,
+            }
+            | Node::Equal {
+                ref mut a,
+                ref mut b,
+            }
+            | Node::MatMul {
+                ref mut a,
+                ref mut b,
+            } => {
+                *a = f(*a);
+                *b = f(*b);
+            }
+            Node::Ewma {
+                ref mut input,
+                ref mut prev,
+                ..
+            } => {
+                *input = f(*input);
+                *prev = f(*prev);
+            }
+            Node::SafeDiv {
+                ref mut numerator,
+                ref mut denominator,
+                ..
+            } => {
+                *numerator = f(*numerator);
+                *denominator = f(*denominator);
+            }
+        }
+        node
+    }
+
+    /// Any numbers that are part of the node itself, rather than coming from its children.
+    pub fn parameters(&self) -> Vec<f64> {
+        match self {
+            Node::Constant(value) => vec![*value],
+            Node::VectorConstant(values) => values.clone(),
+            Node::External { id } => vec![*id as f64],
+            Node::Pow { exponent, .. } => vec![*exponent],
+            Node::Scale { factor, .. } => vec![*factor],
+            Node::Ewma { alpha, .. } => vec![*alpha],
+            Node::Bucketize { edges, .. } => edges.clone(),
+            Node::Lerp { xs, ys, .. } => xs.iter().chain(ys).cloned().collect(),
+            Node::Reshape { shape, .. } => shape.iter().map(|&len| len as f64).collect(),
+            Node::SafeDiv { default, .. } => vec![*default],
+            _ => vec![],
+        }
+    }
+
+    /// Builds a node from its kind, followed by its parameters and then its children, as
+    /// written by `Graph::to_edge_list`.
+    fn from_fields(kind: &str, fields: &[&str], my_index: usize) -> Result<Node, String> {
+        fn parameter<'a>(
+            kind: &str,
+            fields: &'a [&'a str],
+        ) -> Result<(f64, &'a [&'a str]), String> {
+            match fields.split_first() {
+                Some((field, rest)) => field
+                    .parse::<f64>()
+                    .map(|value| (value, rest))
+                    .map_err(|_| format!("invalid number {:?}", field)),
+                None => Err(format!("{} is missing a parameter", kind)),
+            }
+        }
+        let children = |fields: &[&str]| -> Result<Vec<Idx>, String> {
+            fields
+                .iter()
+                .map(|field| {
+                    let child: usize = field
+                        .parse()
+                        .map_err(|_| format!("invalid child {:?}", field))?;
+                    if child >= my_index {
+                        return Err(format!(
+                            "child {} must come before node {}",
+                            child, my_index
+                        ));
+                    }
+                    Ok(Idx(child))
+                })
+                .collect()
+        };
+        let child = |fields: &[&str]| {
+            let children = children(fields)?;
+            if children.len() == 1 {
+                Ok(children[0])
+            } else {
+                Err(format!("{} needs exactly one child", kind))
+            }
+        };
+        let no_children = |fields: &[&str]| {
+            if fields.is_empty() {
+                Ok(())
+            } else {
+                Err(format!("{} doesn't take any children", kind))
+            }
+        };
+
+        match kind {
+            "constant" => {
+                let (value, rest) = parameter(kind, fields)?;
+                no_children(rest)?;
+                Ok(Node::Constant(value))
+            }
+            "vector_constant" => fields
+                .iter()
+                .map(|field| {
+                    field
+                        .parse()
+                        .map_err(|_| format!("invalid number {:?}", field))
+                })
+                .collect::<Result<_, _>>()
+                .map(Node::VectorConstant),
+            "variable" => {
+                no_children(fields)?;
+                Ok(Node::Variable)
+            }
+            "external" => match fields {
+                [id] => id
+                    .parse()
+                    .map(|id| Node::External { id })
+                    .map_err(|_| format!("invalid external id {:?}", id)),
+                _ => Err("external needs exactly one id".to_string()),
+            },
+            "sum" => Ok(Node::Sum {
+                children: children(fields)?,
+            }),
+            "product" => Ok(Node::Product {
+                children: children(fields)?,
+            }),
+            "exp" => Ok(Node::Exp {
+                child: child(fields)?,
+            }),
+            "ln" => Ok(Node::Ln {
+                child: child(fields)?,
+            }),
+            "logsumexp" => Ok(Node::LogSumExp {
+                children: children(fields)?,
+            }),
+            "squared_norm" => Ok(Node::SquaredNorm {
+                children: children(fields)?,
+            }),
+            "tanh" => Ok(Node::Tanh {
+                child: child(fields)?,
+            }),
+            "softplus" => Ok(Node::Softplus {
+                child: child(fields)?,
+            }),
+            "sinh" => Ok(Node::Sinh {
+                child: child(fields)?,
+            }),
+            "cosh" => Ok(Node::Cosh {
+                child: child(fields)?,
+            }),
+            "atan" => Ok(Node::Atan {
+                child: child(fields)?,
+            }),
+            "asin" => Ok(Node::Asin {
+                child: child(fields)?,
+            }),
+            "pow" => {
+                let (exponent, rest) = parameter(kind, fields)?;
+                Ok(Node::Pow {
+                    child: child(rest)?,
+                    exponent,
+                })
+            }
+            "gelu" => Ok(Node::Gelu {
+                child: child(fields)?,
+            }),
+            "identity" => Ok(Node::Identity {
+                child: child(fields)?,
+            }),
+            "memo" => Ok(Node::Memo {
+                child: child(fields)?,
+            }),
+            "sign" => Ok(Node::Sign {
+                child: child(fields)?,
+            }),
+            "normalize" => Ok(Node::Normalize {
+                child: child(fields)?,
+            }),
+            "matmul" => match children(fields)?[..] {
+                [a, b] => Ok(Node::MatMul { a, b }),
+                _ => Err("matmul needs exactly two children".to_string()),
+            },
+            "max" => Ok(Node::Max {
+                children: children(fields)?,
+            }),
+            "select" => match children(fields)?[..] {
+                [condition, if_true, if_false] => Ok(Node::Select {
+                    condition,
+                    if_true,
+                    if_false,
+                }),
+                _ => Err("select needs exactly three children".to_string()),
+            },
+            "modulo" => match children(fields)?[..] {
+                [a, b] => Ok(Node::Modulo { a, b }),
+                _ => Err("modulo needs exactly two children".to_string()),
+            },
+            "safe_div" => {
+                let (default, rest) = parameter(kind, fields)?;
+                match children(rest)?[..] {
+                    [numerator, denominator] => Ok(Node::SafeDiv {
+                        numerator,
+                        denominator,
+                        default,
+                    }),
+                    _ => Err("safe_div needs exactly two children".to_string()),
+                }
+            }
+            "pow_node" => match children(fields)?[..] {
+                [base, exponent] => Ok(Node::PowNode { base, exponent }),
+                _ => Err("pow_node needs exactly two children".to_string()),
+            },
+            "greater" => match children(fields)?[..] {
+                [a, b] => Ok(Node::Greater { a, b }),
+                _ => Err("greater needs exactly two children".to_string()),
+            },
+            "equal" => match children(fields)?[..] {
+                [a, b] => Ok(Node::Equal { a, b }),
+                _ => Err("equal needs exactly two children".to_string()),
+            },
+            "bucketize" => {
+                let (last, edges) = fields
+                    .split_last()
+                    .ok_or_else(|| "bucketize needs exactly one child".to_string())?;
+                let edges = edges
+                    .iter()
+                    .map(|field| {
+                        field
+                            .parse::<f64>()
+                            .map_err(|_| format!("invalid number {:?}", field))
+                    })
+                    .collect::<Result<Vec<f64>, String>>()?;
+                if !is_sorted(&edges) {
+                    return Err("bucketize edges must be sorted".to_string());
+                }
+                Ok(Node::Bucketize {
+                    child: child(&[last])?,
+                    edges,
+                })
+            }
+            "lerp" => {
+                let (last, table) = fields
+                    .split_last()
+                    .ok_or_else(|| "lerp needs exactly one child".to_string())?;
+                let table = table
+                    .iter()
+                    .map(|field| {
+                        field
+                            .parse::<f64>()
+                            .map_err(|_| format!("invalid number {:?}", field))
+                    })
+                    .collect::<Result<Vec<f64>, String>>()?;
+                if table.is_empty() || table.len() % 2 != 0 {
+                    return Err("lerp needs as many xs as ys".to_string());
+                }
+                let (xs, ys) = table.split_at(table.len() / 2);
+                if !is_sorted(xs) {
+                    return Err("lerp xs must be sorted".to_string());
+                }
+                Ok(Node::Lerp {
+                    child: child(&[last])?,
+                    xs: xs.to_vec(),
+                    ys: ys.to_vec(),
+                })
+            }
+            "reshape" => {
+                let (last, shape) = fields
+                    .split_last()
+                    .ok_or_else(|| "reshape needs exactly one child".to_string())?;
+                let shape = shape
+                    .iter()
+                    .map(|field| {
+                        field
+                            .parse::<usize>()
+                            .map_err(|_| format!("invalid length {:?}", field))
+                    })
+                    .collect::<Result<Vec<usize>, String>>()?;
+                Ok(Node::Reshape {
+                    child: child(&[last])?,
+                    shape,
+                })
+            }
+            "ewma" => {
+                let (alpha, rest) = parameter(kind, fields)?;
+                match children(rest)?[..] {
+                    [input, prev] => Ok(Node::Ewma { input, prev, alpha }),
+                    _ => Err("ewma needs exactly two children".to_string()),
+                }
+            }
+            _ if kind.starts_with("reduce_") => match ReduceOp::from_name(&kind[7..]) {
+                Some(op) => Ok(Node::Reduce {
+                    op,
+                    children: children(fields)?,
+                }),
+                None => Err(format!("unknown reduction {:?}", kind)),
+            },
+            "scale" => {
+                let (factor, rest) = parameter(kind, fields)?;
+                Ok(Node::Scale {
+                    child: child(rest)?,
+                    factor,
+                })
+            }
+            "custom" => Err("custom nodes can't be parsed".to_string()),
+            other => Err(format!("unknown node kind {:?}", other)),
+        }
+    }
+
+    /// Parses one line of `Graph::to_edge_list`, which must be for the node at `index`.
+    /// `line_index` counts from zero.
+    fn from_edge_list_line(
+        line: &str,
+        line_index: usize,
+        index: usize,
+    ) -> Result<Node, ParseError> {
+        let error = |message: String| ParseError {
+            line: line_index + 1,
+            message,
+        };
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+
+        let found: usize = fields[0]
+            .parse()
+            .map_err(|_| error(format!("invalid index {:?}", fields[0])))?;
+        if found != index {
+            return Err(error(format!("expected index {}, got {}", index, found)));
+        }
+
+        let kind = fields
+            .get(1)
+            .ok_or_else(|| error("missing node kind".to_string()))?;
+        Node::from_fields(kind, &fields[2..], index).map_err(error)
+    }
+
+    /// The value of this node, given the values of its children. `values` is usually a
+    /// `HashMap`, but anything that can be indexed by `&Idx` works.
+    fn get_value<V>(&self, my_index: &Idx, values: &V) -> f64
+    where
+        V: for<'a> Index<&'a Idx, Output = f64>,
+    {
+        match self {
+            Node::Constant(value) => *value,
+            Node::VectorConstant(_) => {
+                panic!("vector constants can only be evaluated with evaluate_vectors")
+            }
+            Node::Variable | Node::External { .. } => values[my_index],
+            Node::Sum { children } => ReduceOp::Sum.value(children, values),
+            Node::Product { children } => ReduceOp::Product.value(children, values),
+            Node::Max { children } => ReduceOp::Max.value(children, values),
+            Node::Reduce { op, children } => op.value(children, values),
+            Node::Exp { child } => values[child].exp(),
+            Node::Identity { child } | Node::Memo { child } | Node::Reshape { child, .. } => {
+                values[child]
+            }
+            Node::Ln { child } => values[child].ln(),
+            Node::LogSumExp { children } => {
+                let max = children
+                    .iter()
+                    .map(|child| values[child])
+                    .fold(f64::NEG_INFINITY, f64::max);
+                if max.is_infinite() {
+                    return max;
+                }
+                let sum: f64 = children
+                    .iter()
+                    .map(|child| (values[child] - max).exp())
+                    .sum();
+                max + sum.ln()
+            }
+            Node::SquaredNorm { children } => {
+                children.iter().map(|child| values[child].powi(2)).sum()
+            }
+            Node::Tanh { child } => values[child].tanh(),
+            Node::Sinh { child } => values[child].sinh(),
+            Node::Cosh { child } => values[child].cosh(),
+            Node::Atan { child } => values[child].atan(),
+            Node::Asin { child } => values[child].asin(),
+            Node::Softplus { child } => {
+                let x = values[child];
+                x.max(0.0) + (-x.abs()).exp().ln_1p()
+            }
+            Node::Scale { child, factor } => factor * values[child],
+            Node::Pow { child, exponent } => values[child].powf(*exponent),
+            Node::PowNode { base, exponent } => values[base].powf(values[exponent]),
+            Node::Gelu { child } => {
+                let x = values[child];
+                0.5 * x * (1.0 + (GELU_SCALE * (x + GELU_CUBIC * x.powi(3))).tanh())
+            }
+            Node::Select {
+                condition,
+                if_true,
+                if_false,
+            } => {
+                if values[condition] > 0.0 {
+                    values[if_true]
+                } else {
+                    values[if_false]
+                }
+            }
+            Node::Modulo { a, b } => values[a].rem_euclid(values[b]),
+            Node::SafeDiv {
+                numerator,
+                denominator,
+                default,
+            } => {
+                if values[denominator] == 0.0 {
+                    *default
+                } else {
+                    values[numerator] / values[denominator]
+                }
+            }
+            Node::Greater { a, b } => {
+                if values[a] > values[b] {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Node::Equal { a, b } => {
+                if values[a] == values[b] {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Node::Bucketize { child, edges } => {
+                edges.iter().filter(|&&edge| edge <= values[child]).count() as f64
+            }
+            Node::Sign { child } => sign(values[child]),
+            Node::Normalize { .. } => {
+                panic!("normalize nodes can only be evaluated with evaluate_vectors")
+            }
+            Node::MatMul { .. } => {
+                panic!("matmul nodes can only be evaluated with evaluate_vectors")
+            }
+            Node::Lerp { child, xs, ys } => interpolate(xs, ys, values[child]).0,
+            Node::Ewma { input, prev, alpha } => {
+                alpha * values[input] + (1.0 - alpha) * values[prev]
+            }
+            Node::Custom { children, eval, .. } => {
+                let inputs: Vec<f64> = children.iter().map(|child| values[child]).collect();
+                eval(&inputs)
+            }
+        }
+    }
+
+    /// The partial derivative of this node with respect to each of its children, in the same
+    /// order as `children`. This is all that reverse mode needs to know about a node.
+    fn local_gradients(&self, my_index: &Idx, values: &HashMap<Idx, f64>) -> Vec<f64> {
+        self.local_gradients_with_eps(my_index, values, 0.0)
+    }
+
+    /// Like `local_gradients`, but adds `epsilon` to the denominators of derivatives that
+    /// divide by one of the children.
+    fn local_gradients_with_eps(
+        &self,
+        my_index: &Idx,
+        values: &HashMap<Idx, f64>,
+        epsilon: f64,
+    ) -> Vec<f64> {
+        match self {
+            Node::Constant(_)
+            | Node::VectorConstant(_)
+            | Node::Variable
+            | Node::External { .. } => vec![],
+            Node::Sum { children } => {
+                ReduceOp::Sum.local_gradients(children, values, values[my_index])
+            }
+            Node::Product { children } => {
+                ReduceOp::Product.local_gradients(children, values, values[my_index])
+            }
+            Node::Max { children } => {
+                ReduceOp::Max.local_gradients(children, values, values[my_index])
+            }
+            Node::Reduce { op, children } => op.local_gradients(children, values, values[my_index]),
+            Node::Exp { .. } => vec![values[my_index]],
+            Node::Identity { .. } | Node::Memo { .. } | Node::Reshape { .. } => vec![1.0],
+            Node::Ln { child } => vec![1.0 / (values[child] + epsilon)],
+            Node::LogSumExp { children } => children
+                .iter()
+                .map(|child| (values[child] - values[my_index]).exp())
+                .collect(),
+            Node::SquaredNorm { children } => {
+                children.iter().map(|child| 2.0 * values[child]).collect()
+            }
+            Node::Tanh { .. } => vec![1.0 - values[my_index].powi(2)],
+            Node::Softplus { child } => vec![0.5 * (1.0 + (0.5 * values[child]).tanh())],
+            Node::Sinh { child } => vec![values[child].cosh()],
+            Node::Cosh { child } => vec![values[child].sinh()],
+            Node::Atan { child } => vec![1.0 / (1.0 + values[child].powi(2))],
+            Node::Asin { child } => vec![1.0 / (1.0 - values[child].powi(2)).sqrt()],
+            Node::Scale { factor, .. } => vec![*factor],
+            Node::Pow { child, exponent } => vec![exponent * values[child].powf(exponent - 1.0)],
+            Node::PowNode { base, exponent } => {
+                let (base, exponent) = (values[base], values[exponent]);
+                let d_exponent = if base > 0.0 {
+                    base.powf(exponent) * base.ln()
+                } else {
+                    0.0
+                };
+                vec![exponent * base.powf(exponent - 1.0), d_exponent]
+            }
+            Node::Gelu { child } => {
+                let x = values[child];
+                let tanh = (GELU_SCALE * (x + GELU_CUBIC * x.powi(3))).tanh();
+                let inner_slope = GELU_SCALE * (1.0 + 3.0 * GELU_CUBIC * x.powi(2));
+                vec![0.5 * (1.0 + tanh) + 0.5 * x * (1.0 - tanh.powi(2)) * inner_slope]
+            }
+            Node::Select { condition, .. } => {
+                // The condition never gets any gradient, even at the threshold
+                if values[condition] > 0.0 {
+                    vec![0.0, 1.0, 0.0]
+                } else {
+                    vec![0.0, 0.0, 1.0]
+                }
+            }
+            Node::Modulo { .. } => vec![1.0, 0.0],
+            Node::SafeDiv {
+                numerator,
+                denominator,
+                ..
+            } => {
+                let d = values[denominator];
+                if d == 0.0 {
+                    vec![0.0, 0.0]
+                } else {
+                    vec![1.0 / d, -values[numerator] / (d * d)]
+                }
+            }
+            Node::Greater { .. } | Node::Equal { .. } => vec![0.0, 0.0],
+            Node::Bucketize { .. } | Node::Sign { .. } => vec![0.0],
+            Node::Normalize { .. } => panic!("normalize nodes can't be differentiated"),
+            Node::MatMul { .. } => panic!("matmul nodes need vector_gradients"),
+            Node::Lerp { child, xs, ys } => vec![interpolate(xs, ys, values[child]).1],
+            Node::Ewma { alpha, .. } => vec![*alpha, 1.0 - alpha],
+            Node::Custom { children, grad, .. } => {
+                let grad = grad.as_ref().expect(NEEDS_GRAD);
+                let inputs: Vec<f64> = children.iter().map(|child| values[child]).collect();
+                grad(&inputs)
+            }
+        }
+    }
+
+    /// Pushes the nodes that compute the derivative of this node into `graph`, returning the
+    /// index of the final one. `derivatives` must contain the derivative of every child.
+    fn derivative(
+        &self,
+        my_index: Idx,
+        wrt: &HashSet<Idx>,
+        derivatives: &HashMap<Idx, Idx>,
+        graph: &mut Graph,
+    ) -> Idx {
+        match self {
+            Node::Constant(_) => graph.push(Node::Constant(0.0)),
+            Node::VectorConstant(values) => {
+                graph.push(Node::VectorConstant(vec![0.0; values.len()]))
+            }
+            Node::Variable | Node::External { .. } => {
+                if wrt.contains(&my_index) {
+                    graph.push(Node::Constant(1.0))
+                } else {
+                    graph.push(Node::Constant(0.0))
+                }
+            }
+            Node::Sum { children } => ReduceOp::Sum.derivative(children, derivatives, graph),
+            Node::Product { children } => {
+                ReduceOp::Product.derivative(children, derivatives, graph)
+            }
+            Node::Max { children } => ReduceOp::Max.derivative(children, derivatives, graph),
+            Node::Reduce { op, children } => op.derivative(children, derivatives, graph),
+            Node::Exp { child } => graph.push(my_index * derivatives[child]),
+            Node::Identity { child } | Node::Memo { child } | Node::Reshape { child, .. } => {
+                derivatives[child]
+            }
+            Node::Ln { child } => {
+                let reciprocal = graph.push(Node::Pow {
+                    child: *child,
+                    exponent: -1.0,
+                });
+                graph.push(reciprocal * derivatives[child])
+            }
+            Node::Scale { child, factor } => graph.push(Node::Scale {
+                child: derivatives[child],
+                factor: *factor,
+            }),
+            Node::LogSumExp { ref children } => {
+                // Each child is weighted by its softmax weight, exp(x_i - logsumexp)
+                let minus_one = graph.push(Node::Constant(-1.0));
+                let minus_me = graph.push(minus_one * my_index);
+                let terms = children
+                    .iter()
+                    .map(|child| {
+                        let shifted = graph.push(*child + minus_me);
+                        let weight = graph.push(Node::Exp { child: shifted });
+                        graph.push(weight * derivatives[child])
+                    })
+                    .collect();
+                graph.push(Node::Sum { children: terms })
+            }
+            Node::SquaredNorm { children } => {
+                let terms = children
+                    .iter()
+                    .map(|child| {
+                        let product = graph.push(*child * derivatives[child]);
+                        graph.push(product * 2.0)
+                    })
+                    .collect();
+                graph.push(Node::Sum { children: terms })
+            }
+            Node::Tanh { child } => {
+                // tanh'(x) = 1 - tanh(x)^2
+                let squared = graph.push(Node::Pow {
+                    child: my_index,
+                    exponent: 2.0,
+                });
+                let minus_one = graph.push(Node::Constant(-1.0));
+                let minus_squared = graph.push(minus_one * squared);
+                let one = graph.push(Node::Constant(1.0));
+                let slope = graph.push(one + minus_squared);
+                graph.push(slope * derivatives[child])
+            }
+            Node::Softplus { child } => {
+                // The sigmoid, written stably as (1 + tanh(x / 2)) / 2
+                let half_x = graph.push(*child * 0.5);
+                let tanh = graph.push(Node::Tanh { child: half_x });
+                let one = graph.push(Node::Constant(1.0));
+                let shifted = graph.push(one + tanh);
+                let sigmoid = graph.push(shifted * 0.5);
+                graph.push(sigmoid * derivatives[child])
+            }
+            Node::Sinh { child } => {
+                let cosh = graph.push(Node::Cosh { child: *child });
+                graph.push(cosh * derivatives[child])
+            }
+            Node::Cosh { child } => {
+                let sinh = graph.push(Node::Sinh { child: *child });
+                graph.push(sinh * derivatives[child])
+            }
+            Node::Atan { child } => {
+                // atan'(x) = (1 + x^2)^-1
+                let squared = graph.push(Node::Pow {
+                    child: *child,
+                    exponent: 2.0,
+                });
+                let one = graph.push(Node::Constant(1.0));
+                let denominator = graph.push(one + squared);
+                let slope = graph.push(Node::Pow {
+                    child: denominator,
+                    exponent: -1.0,
+                });
+                graph.push(slope * derivatives[child])
+            }
+            Node::Asin { child } => {
+                // asin'(x) = (1 - x^2)^-0.5
+                let squared = graph.push(Node::Pow {
+                    child: *child,
+                    exponent: 2.0,
+                });
+                let minus_squared = graph.push(squared * -1.0);
+                let one = graph.push(Node::Constant(1.0));
+                let difference = graph.push(one + minus_squared);
+                let slope = graph.push(Node::Pow {
+                    child: difference,
+                    exponent: -0.5,
+                });
+                graph.push(slope * derivatives[child])
+            }
+            Node::Pow { child, exponent } => {
+                let coefficient = graph.push(Node::Constant(*exponent));
+                let lowered = graph.push(Node::Pow {
+                    child: *child,
+                    exponent: exponent - 1.0,
+                });
+                graph.push(Node::Product {
+                    children: vec![coefficient, lowered, derivatives[child]],
+                })
+            }
+            Node::PowNode { base, exponent } => {
+                // exponent * base^(exponent - 1) * base', which is the same as the second term
+                // of the derivative but doesn't divide by a zero base
+                let minus_one = graph.push(Node::Constant(-1.0));
+                let lowered_exponent = graph.push(*exponent + minus_one);
+                let lowered = graph.push(Node::PowNode {
+                    base: *base,
+                    exponent: lowered_exponent,
+                });
+                let base_term = graph.push(Node::Product {
+                    children: vec![*exponent, lowered, derivatives[base]],
+                });
+
+                // base^exponent * ln(base) * exponent', or zero if the base isn't positive
+                let ln = graph.push(Node::Ln { child: *base });
+                let exponent_term = graph.push(Node::Product {
+                    children: vec![my_index, ln, derivatives[exponent]],
+                });
+                let zero = graph.push(Node::Constant(0.0));
+                let guarded = graph.push(Node::Select {
+                    condition: *base,
+                    if_true: exponent_term,
+                    if_false: zero,
+                });
+                graph.push(base_term + guarded)
+            }
+            Node::Gelu { child } => {
+                // Differentiate the expanded form. The expansion only refers to the child and to
+                // its own nodes, so that's all the memo needs.
+                let start = graph.len();
+                let expanded = graph.push_gelu(*child);
+                let mut expansion_derivatives = HashMap::new();
+                expansion_derivatives.insert(*child, derivatives[child]);
+                for index in start..=expanded.0 {
+                    let index = Idx(index);
+                    let node = graph[index].clone();
+                    let derivative = node.derivative(index, wrt, &expansion_derivatives, graph);
+                    expansion_derivatives.insert(index, derivative);
+                }
+                expansion_derivatives[&expanded]
+            }
+            Node::Select {
+                condition,
+                if_true,
+                if_false,
+            } => graph.push(Node::Select {
+                condition: *condition,
+                if_true: derivatives[if_true],
+                if_false: derivatives[if_false],
+            }),
+            Node::Modulo { a, .. } => derivatives[a],
+            Node::SafeDiv {
+                numerator,
+                denominator,
+                ..
+            } => {
+                // (n / d)' = (n' - (n / d) * d') / d, which is another safe division
+                let product = graph.push(my_index * derivatives[denominator]);
+                let negated = graph.push(product * -1.0);
+                let difference = graph.push(derivatives[numerator] + negated);
+                graph.push(Node::SafeDiv {
+                    numerator: difference,
+                    denominator: *denominator,
+                    default: 0.0,
+                })
+            }
+            Node::Greater { .. }
+            | Node::Equal { .. }
+            | Node::Bucketize { .. }
+            | Node::Sign { .. } => graph.push(Node::Constant(0.0)),
+            Node::Normalize { .. } => panic!("normalize nodes can't be differentiated"),
+            Node::MatMul { .. } => panic!("matmul nodes need vector_gradients"),
+            Node::Lerp { child, xs, ys } => {
+                let (xs, ys) = slope_table(xs, ys);
+                let slope = graph.push(Node::Lerp {
+                    child: *child,
+                    xs,
+                    ys,
+                });
+                graph.push(slope * derivatives[child])
+            }
+            Node::Ewma { input, prev, alpha } => {
+                let input_term = graph.push(derivatives[input] * *alpha);
+                let prev_term = graph.push(derivatives[prev] * (1.0 - alpha));
+                graph.push(input_term + prev_term)
+            }
+            Node::Custom { children, grad, .. } => {
+                let grad = grad.as_ref().expect(NEEDS_GRAD);
+                let terms = children
+                    .iter()
+                    .enumerate()
+                    .map(|(i, child)| {
+                        let grad = grad.clone();
+                        let partial = graph
+                            .push(Node::custom_partial(children.clone(), move |inputs| {
+                                grad(inputs)[i]
+                            }));
+                        graph.push(partial * derivatives[child])
+                    })
+                    .collect();
+                graph.push(Node::Sum { children: terms })
+            }
+        }
+    }
+}
+
+/// The values of the nodes that an output depends on, and the output's gradient with respect to
+/// each of them
+type ValuesAndGradients = (HashMap<Idx, f64>, HashMap<Idx, f64>);
+
+/// Everything that differentiates a node checks for custom nodes without a gradient first
+const NEEDS_GRAD: &str = "custom nodes without a gradient are checked for before differentiating";
+
+/// `-1`, `0`, or `1`, unlike `f64::signum`, which is never `0`. NaN stays NaN.
+pub(crate) fn sign(value: f64) -> f64 {
+    if value > 0.0 {
+        1.0
+    } else if value < 0.0 {
+        -1.0
+    } else {
+        value * 0.0
+    }
+}
+
+/// The value at `x` of the piecewise-linear function through `(xs, ys)`, and its slope there.
+/// Outside of `xs`, the function holds the first or last `y`.
+fn interpolate(xs: &[f64], ys: &[f64], x: f64) -> (f64, f64) {
+    let last = xs.len() - 1;
+    if x <= xs[0] {
+        (ys[0], 0.0)
+    } else if x >= xs[last] {
+        (ys[last], 0.0)
+    } else {
+        // x is strictly inside the table, so the segment to its right exists and isn't empty
+        let i = xs.iter().filter(|&&edge| edge <= x).count() - 1;
+        let slope = (ys[i + 1] - ys[i]) / (xs[i + 1] - xs[i]);
+        (ys[i] + slope * (x - xs[i]), slope)
+    }
+}
+
+/// A table for `Lerp` that looks up the slope of the segment that `x` falls in, matching the
+/// slope from `interpolate`. Each slope is repeated at both ends of its segment so that it's
+/// flat in between, and the steps at the points are exact because `interpolate` picks the
+/// segment to the right. Zeros at both ends cover the outside of the table.
+fn slope_table(xs: &[f64], ys: &[f64]) -> (Vec<f64>, Vec<f64>) {
+    let mut slope_xs = vec![xs[0]];
+    let mut slope_ys = vec![0.0];
+    for i in 0..xs.len() - 1 {
+        // interpolate never picks an empty segment, so its slope doesn't matter
+        let slope = if xs[i + 1] > xs[i] {
+            (ys[i + 1] - ys[i]) / (xs[i + 1] - xs[i])
+        } else {
+            0.0
+        };
+        slope_xs.extend_from_slice(&[xs[i], xs[i + 1]]);
+        slope_ys.extend_from_slice(&[slope, slope]);
+    }
+    slope_xs.push(xs[xs.len() - 1]);
+    slope_ys.push(0.0);
+    (slope_xs, slope_ys)
+}
+
+fn is_sorted(values: &[f64]) -> bool {
+    values.windows(2).all(|pair| pair[0] <= pair[1])
+}
+
+/// The constants in the tanh approximation of GELU
+const GELU_SCALE: f64 = 0.797_884_560_802_865_4; // sqrt(2 / pi)
+const GELU_CUBIC: f64 = 0.044_715;
+
+/// Describes why a textual graph couldn't be loaded. Lines are numbered from 1.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for ParseError {}
+
+/// Something that went wrong while inspecting or editing a graph.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GraphError {
+    /// The index doesn't refer to a node in this graph.
+    OutOfBounds(Idx),
+    /// The operation only makes sense for a `Variable` node.
+    NotAVariable(Idx),
+    /// The operation only makes sense for a `Constant` node.
+    NotAConstant(Idx),
+    /// The children of the node are vectors of different lengths, or of the wrong length.
+    LengthMismatch(Idx),
+    /// The node can't be evaluated exactly with rationals.
+    NotRational(Idx),
+    /// The node can't be evaluated in fixed point.
+    NotFixedPoint(Idx),
+    /// The node's value doesn't fit in the number type it's being evaluated with.
+    Overflow(Idx),
+    /// The node only knows its first derivatives, so it can't be differentiated twice. This is
+    /// either a custom node, or one of the partial derivatives that differentiating a custom
+    /// node creates.
+    NotTwiceDifferentiable(Idx),
+    /// The node has no equivalent in the trait-based graph.
+    Unconvertible(Idx),
+    /// The operation only makes sense for a node that takes a list of children.
+    NotVariadic(Idx),
+    /// `child` doesn't come before `node`, so using it as a child could make a cycle.
+    ForwardReference { node: Idx, child: Idx },
+    /// `node` would be left referring to `child`, which doesn't exist.
+    DanglingChild { node: Idx, child: Idx },
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GraphError::OutOfBounds(idx) => write!(f, "node {} is not in the graph", idx.0),
+            GraphError::NotAVariable(idx) => write!(f, "node {} is not a variable", idx.0),
+            GraphError::NotAConstant(idx) => write!(f, "node {} is not a constant", idx.0),
+            GraphError::LengthMismatch(idx) => {
+                write!(f, "node {} combines vectors of different lengths", idx.0)
+            }
+            GraphError::NotRational(idx) => {
+                write!(f, "node {} can't be evaluated with rationals", idx.0)
+            }
+            GraphError::NotFixedPoint(idx) => {
+                write!(f, "node {} can't be evaluated in fixed point", idx.0)
+            }
+            GraphError::Overflow(idx) => write!(f, "node {} overflowed", idx.0),
+            GraphError::NotTwiceDifferentiable(idx) => {
+                write!(f, "node {} can't be differentiated twice", idx.0)
+            }
+            GraphError::Unconvertible(idx) => {
+                write!(
+                    f,
+                    "node {} has no equivalent in the trait-based graph",
+                    idx.0
+                )
+            }
+            GraphError::NotVariadic(idx) => {
+                write!(f, "node {} doesn't take a list of children", idx.0)
+            }
+            GraphError::ForwardReference { node, child } => write!(
+                f,
+                "node {} can't use node {} as a child because it comes later",
+                node.0, child.0
+            ),
+            GraphError::DanglingChild { node, child } => write!(
+                f,
+                "node {} refers to child {}, which doesn't exist",
+                node.0, child.0
+            ),
+        }
+    }
+}
+
+impl Error for GraphError {}
+
+/// Describes why a graph wasn't evaluated.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EvalError {
+    /// Evaluating would compute `nodes` nodes, more than the `max_nodes` allowed.
+    BudgetExceeded { nodes: usize, max_nodes: usize },
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EvalError::BudgetExceeded { nodes, max_nodes } => write!(
+                f,
+                "evaluating {} nodes exceeds the budget of {}",
+                nodes, max_nodes
+            ),
+        }
+    }
+}
+
+impl Error for EvalError {}
+
+/// This helps us to represent the idea that only a subset of the nodes in a graph might be
+/// relevant for a particular computation. The indices in a Subgraph are ordered such that a
+/// child always comes before one of its parents.
+#[derive(Clone, Debug)]
+pub struct Subgraph {
+    indices: Vec<Idx>,
+}
+
+impl Subgraph {
+    fn new(indices_unsorted: impl Iterator<Item = Idx>) -> Self {
+        let mut indices: Vec<Idx> = indices_unsorted.collect();
+
+        // This is an easy way to enforce the order condition
+        indices.sort_unstable_by_key(|index| index.0);
+        Self { indices }
+    }
+
+    pub fn indices(&self) -> &[Idx] {
+        &self.indices
+    }
+}
+
+/// A point in a graph's history that it can be restored to. This is just the number of nodes,
+/// since nodes are only ever appended.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Checkpoint {
+    len: usize,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Graph {
+    nodes: Vec<Node>,
+    metadata: Metadata,
+}
+
+impl Graph {
+    pub fn push(&mut self, node: Node) -> Idx {
+        self.nodes.push(node);
+        Idx(self.nodes.len() - 1)
+    }
+
+    /// Changes the value of an existing constant, so that parameters can be tuned without
+    /// rebuilding the graph.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, GraphError, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Constant(1.0));
+    /// let b = g.push(Node::Variable);
+    /// let c = g.push(a + b);
+    /// let values: HashMap<_, _> = vec![(b, 2.0)].into_iter().collect();
+    /// assert_eq!(3.0, g.evaluate(values.clone())[&c]);
+    ///
+    /// g.set_constant(a, 10.0).unwrap();
+    /// assert_eq!(12.0, g.evaluate(values)[&c]);
+    ///
+    /// assert_eq!(Err(GraphError::NotAConstant(b)), g.set_constant(b, 1.0));
+    /// ```
+    pub fn set_constant(&mut self, idx: Idx, value: f64) -> Result<(), GraphError> {
+        match self.nodes.get_mut(idx.0) {
+            Some(Node::Constant(old_value)) => {
+                *old_value = value;
+                Ok(())
+            }
+            Some(_) => Err(GraphError::NotAConstant(idx)),
+            None => Err(GraphError::OutOfBounds(idx)),
+        }
+    }
+
+    /// Replaces the children of an existing node that takes a list of children, such as a sum
+    /// or a product. The new children must all come before the node, which keeps the graph
+    /// acyclic. A custom node's closures expect a certain number of inputs, so it can only get
+    /// a new list of the same length.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, GraphError, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Variable);
+    /// let sum = g.push(a + b);
+    /// let later = g.push(Node::Constant(1.0));
+    ///
+    /// g.rewire(sum, vec![a, a, b]).unwrap();
+    /// let values: HashMap<_, _> = vec![(a, 2.0), (b, 3.0)].into_iter().collect();
+    /// assert_eq!(7.0, g.evaluate(values)[&sum]);
+    ///
+    /// assert_eq!(
+    ///     Err(GraphError::ForwardReference { node: sum, child: later }),
+    ///     g.rewire(sum, vec![a, later])
+    /// );
+    /// assert_eq!(Err(GraphError::NotVariadic(a)), g.rewire(a, vec![]));
+    ///
+    /// let hypot = g.push(Node::custom(vec![a, b], |x| x[0].hypot(x[1]), |_| vec![0.0, 0.0]));
+    /// g.rewire(hypot, vec![b, a]).unwrap();
+    /// assert_eq!(Err(GraphError::NotVariadic(hypot)), g.rewire(hypot, vec![a, b, b]));
+    /// ```
+    pub fn rewire(&mut self, idx: Idx, new_children: Vec<Idx>) -> Result<(), GraphError> {
+        if let Some(&child) = new_children.iter().find(|child| child.0 >= idx.0) {
+            return Err(GraphError::ForwardReference { node: idx, child });
+        }
+        match self.nodes.get_mut(idx.0) {
+            Some(Node::Sum { children })
+            | Some(Node::Product { children })
+            | Some(Node::LogSumExp { children })
+            | Some(Node::SquaredNorm { children })
+            | Some(Node::Max { children })
+            | Some(Node::Reduce { children, .. }) => {
+                *children = new_children;
+                Ok(())
+            }
+            Some(Node::Custom { children, .. }) if children.len() == new_children.len() => {
+                *children = new_children;
+                Ok(())
+            }
+            Some(_) => Err(GraphError::NotVariadic(idx)),
+            None => Err(GraphError::OutOfBounds(idx)),
+        }
+    }
+
+    /// Makes every node that uses `old` as a child use `new` instead. `old` itself stays in the
+    /// graph. This fails without changing anything if a node using `old` comes before `new`,
+    /// since that could create a cycle.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, GraphError, Node};
+    ///
+    /// // (a + b) * 2, and then a * b
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Variable);
+    /// let sum = g.push(a + b);
+    /// let output = g.push(sum * 2.0);
+    /// let product = g.push(a * b);
+    /// let values: HashMap<_, _> = vec![(a, 2.0), (b, 3.0)].into_iter().collect();
+    /// assert_eq!(10.0, g.evaluate(values.clone())[&output]);
+    ///
+    /// // a * b needs to come before the nodes that use it
+    /// assert_eq!(
+    ///     Err(GraphError::ForwardReference { node: output, child: product }),
+    ///     g.substitute(sum, product)
+    /// );
+    ///
+    /// // Replace b with a
+    /// g.substitute(b, a).unwrap();
+    /// assert_eq!(8.0, g.evaluate(values)[&output]);
+    /// ```
+    pub fn substitute(&mut self, old: Idx, new: Idx) -> Result<(), GraphError> {
+        if new.0 >= self.nodes.len() {
+            return Err(GraphError::OutOfBounds(new));
+        }
+        for (i, node) in self.nodes.iter().enumerate() {
+            if i <= new.0 && node.children().contains(&old) {
+                return Err(GraphError::ForwardReference {
+                    node: Idx(i),
+                    child: new,
+                });
+            }
+        }
+
+        for node in &mut self.nodes {
+            if node.children().contains(&old) {
+                *node = node.map_children(|child| if child == old { new } else { child });
+            }
+        }
+        Ok(())
+    }
+
+    /// Makes every node that uses the variable `remove` use the variable `keep` instead, for
+    /// when two variables turn out to be the same input. Like `substitute`, `remove` stays in
+    /// the graph, but nothing uses it anymore.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, GraphError, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Variable);
+    /// let product = g.push(a * b);
+    /// assert_eq!(Err(GraphError::NotAVariable(product)), g.merge_variables(a, product));
+    ///
+    /// g.merge_variables(a, b).unwrap();
+    /// assert_eq!(vec![a], g.relevant_variables(product).into_iter().collect::<Vec<_>>());
+    /// let values: HashMap<_, _> = vec![(a, 3.0)].into_iter().collect();
+    /// assert_eq!(9.0, g.evaluate_subgraph(g.subgraph_for(&[product]), values)[&product]);
+    /// ```
+    pub fn merge_variables(&mut self, keep: Idx, remove: Idx) -> Result<(), GraphError> {
+        for &idx in &[keep, remove] {
+            match self.nodes.get(idx.0) {
+                None => return Err(GraphError::OutOfBounds(idx)),
+                Some(Node::Variable) => {}
+                Some(_) => return Err(GraphError::NotAVariable(idx)),
+            }
+        }
+        self.substitute(remove, keep)
+    }
+
+    /// Removes every node from `len` onwards, undoing speculative pushes. This fails if a node
+    /// that would be kept refers to one that would be removed, which can only happen if an Idx
+    /// from a different graph was pushed. Truncating to at least the current length does
+    /// nothing.
+    ///
+    /// Any Idx that pointed at a removed node becomes invalid.
+    ///
+    /// ```
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, GraphError, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    ///
+    /// // Start building something, then change our minds
+    /// let len = g.len();
+    /// let two = g.push(Node::Constant(2.0));
+    /// g.push(a * two);
+    /// g.truncate(len).unwrap();
+    /// assert_eq!(1, g.len());
+    ///
+    /// // A child that isn't in front of its parent can't be removed
+    /// let mut other = Graph::default();
+    /// other.push(Node::Variable);
+    /// other.push(Node::Variable);
+    /// let far_away = other.push(Node::Variable);
+    /// let sum = g.push(a + far_away);
+    /// g.push(Node::Variable);
+    /// assert_eq!(
+    ///     Err(GraphError::DanglingChild { node: sum, child: far_away }),
+    ///     g.truncate(2)
+    /// );
+    /// ```
+    pub fn truncate(&mut self, len: usize) -> Result<(), GraphError> {
+        for (i, node) in self.nodes.iter().enumerate().take(len) {
+            if let Some(child) = node.children().into_iter().find(|child| child.0 >= len) {
+                return Err(GraphError::DanglingChild {
+                    node: Idx(i),
+                    child,
+                });
+            }
+        }
+
+        self.nodes.truncate(len);
+        self.metadata.truncate(len);
+        Ok(())
+    }
+
+    /// Remembers the current state of the graph so that `restore` can return to it. Together,
+    /// these make it cheap to build part of a graph speculatively.
+    ///
+    /// ```
+    /// use std::collections::HashSet;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(a * a);
+    ///
+    /// let checkpoint = g.checkpoint();
+    /// let wrt: HashSet<_> = vec![a].into_iter().collect();
+    /// g.derivative(b, wrt).unwrap();
+    /// assert!(g.len() > 2);
+    ///
+    /// g.restore(checkpoint).unwrap();
+    /// assert_eq!(2, g.len());
+    /// ```
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint { len: self.len() }
+    }
+
+    /// Removes every node added since `checkpoint` was taken. Like `truncate`, this fails if a
+    /// node from before the checkpoint refers to one from after it.
+    pub fn restore(&mut self, checkpoint: Checkpoint) -> Result<(), GraphError> {
+        self.truncate(checkpoint.len)
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Checks that every node only refers to children that come before it, which is what
+    /// evaluation and differentiation rely on. `push` doesn't check this, so an index from a
+    /// different graph can sneak in.
+    ///
+    /// ```
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, GraphError, Node};
+    ///
+    /// let mut other = Graph::default();
+    /// other.push(Node::Variable);
+    /// let far_away = other.push(Node::Variable);
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// assert_eq!(Ok(()), g.validate());
+    ///
+    /// let sum = g.push(a + far_away);
+    /// assert_eq!(
+    ///     Err(GraphError::ForwardReference { node: sum, child: far_away }),
+    ///     g.validate()
+    /// );
+    /// ```
+    pub fn validate(&self) -> Result<(), GraphError> {
+        for (i, node) in self.nodes.iter().enumerate() {
+            if let Some(child) = node.children().into_iter().find(|child| child.0 >= i) {
+                return Err(GraphError::ForwardReference {
+                    node: Idx(i),
+                    child,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Estimates the bytes of heap memory used by the nodes, including any `children` vectors.
+    /// This counts allocated capacity, so it can be more than the nodes strictly need.
+    ///
+    /// ```
+    /// use std::mem;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Idx, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Constant(1.0));
+    /// let b = g.push(Node::Variable);
+    /// g.push(a + b);
+    ///
+    /// assert!(g.memory_bytes() >= 3 * mem::size_of::<Node>() + 2 * mem::size_of::<Idx>());
+    /// ```
+    pub fn memory_bytes(&self) -> usize {
+        self.nodes.capacity() * mem::size_of::<Node>()
+            + self.nodes.iter().map(Node::heap_bytes).sum::<usize>()
+    }
+
+    /// The nodes without any children, which are the constants and variables.
+    ///
+    /// ```
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// // The DAG from the start of the post
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Sum { children: vec![a] });
+    /// let c = g.push(Node::Sum { children: vec![a, b] });
+    /// g.push(Node::Sum { children: vec![a, b, c] });
+    ///
+    /// assert_eq!(vec![a], g.leaves().collect::<Vec<_>>());
+    /// ```
+    pub fn leaves(&self) -> impl Iterator<Item = Idx> + '_ {
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| node.children().is_empty())
+            .map(|(i, _)| Idx(i))
+    }
+
+    /// The nodes that no other node uses as a child. These are the graph's outputs, and a graph
+    /// can have more than one of them.
+    ///
+    /// ```
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Variable);
+    /// let sum = g.push(a + b);
+    /// let product = g.push(a * b);
+    ///
+    /// assert_eq!(vec![sum, product], g.roots());
+    /// ```
+    pub fn roots(&self) -> Vec<Idx> {
+        let mut referenced = vec![false; self.nodes.len()];
+        for node in &self.nodes {
+            for child in node.children() {
+                referenced[child.0] = true;
+            }
+        }
+        (0..self.nodes.len())
+            .filter(|&i| !referenced[i])
+            .map(Idx)
+            .collect()
+    }
+
+    /// For each node, lists the nodes that use it as a child, in index order. A node that uses
+    /// the same child twice is listed twice.
+    ///
+    /// ```
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// // d = (1 + b) * b
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Constant(1.0));
+    /// let b = g.push(Node::Variable);
+    /// let c = g.push(a + b);
+    /// let d = g.push(c * b);
+    ///
+    /// assert_eq!(vec![vec![c], vec![c, d], vec![d], vec![]], g.parents());
+    /// ```
+    pub fn parents(&self) -> Vec<Vec<Idx>> {
+        let mut parents = vec![Vec::new(); self.nodes.len()];
+        for (i, node) in self.nodes.iter().enumerate() {
+            for child in node.children() {
+                parents[child.0].push(Idx(i));
+            }
+        }
+        parents
+    }
+
+    /// Numbers each node by how far it is from the leaves: leaves are level 0, and every other
+    /// node is one more than its highest child. Nodes on the same level don't depend on each
+    /// other, so they could be evaluated in parallel.
+    ///
+    /// ```
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// // d = (1 + b) * b
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Constant(1.0));
+    /// let b = g.push(Node::Variable);
+    /// let c = g.push(a + b);
+    /// g.push(c * b);
+    ///
+    /// assert_eq!(vec![0, 0, 1, 2], g.levels());
+    /// ```
+    pub fn levels(&self) -> Vec<usize> {
+        let mut levels: Vec<usize> = Vec::with_capacity(self.nodes.len());
+        for node in &self.nodes {
+            let level = node
+                .children()
+                .iter()
+                .map(|child| levels[child.0] + 1)
+                .max()
+                .unwrap_or(0);
+            levels.push(level);
+        }
+        levels
+    }
+
+    /// Groups the nodes into batches by `levels`, lowest level first. The nodes in a batch
+    /// don't depend on each other, and only depend on nodes in earlier batches, so evaluating
+    /// the batches in order, each one in parallel, is a valid schedule.
+    ///
+    /// ```
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// // 2 * a + a * b
+    /// let mut g = Graph::default();
+    /// let two = g.push(Node::Constant(2.0));
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Variable);
+    /// let two_a = g.push(two * a);
+    /// let a_b = g.push(a * b);
+    /// let sum = g.push(two_a + a_b);
+    ///
+    /// let plan = g.evaluation_plan();
+    /// assert_eq!(vec![vec![two, a, b], vec![two_a, a_b], vec![sum]], plan);
+    ///
+    /// for (i, batch) in plan.iter().enumerate() {
+    ///     for &node in batch {
+    ///         for child in g[node].children() {
+    ///             assert!(plan[..i].iter().any(|earlier| earlier.contains(&child)));
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn evaluation_plan(&self) -> Vec<Vec<Idx>> {
+        let mut plan: Vec<Vec<Idx>> = Vec::new();
+        for (i, level) in self.levels().into_iter().enumerate() {
+            if plan.len() <= level {
+                plan.resize(level + 1, Vec::new());
+            }
+            plan[level].push(Idx(i));
+        }
+        plan
+    }
+
+    /// Estimates the floating-point operations in one evaluation of the whole graph. Leaves are
+    /// free, combining n children takes n - 1 operations, and functions like `exp`, as well as
+    /// comparisons, count as one operation each. Custom nodes are assumed to take one.
+    ///
+    /// ```
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// // 2 * a + a * b
+    /// let mut g = Graph::default();
+    /// let two = g.push(Node::Constant(2.0));
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Variable);
+    /// let left = g.push(two * a);
+    /// let right = g.push(a * b);
+    /// g.push(left + right);
+    ///
+    /// assert_eq!(3, g.flop_count());
+    /// ```
+    pub fn flop_count(&self) -> usize {
+        self.nodes
+            .iter()
+            .map(|node| match node {
+                Node::Constant(_)
+                | Node::VectorConstant(_)
+                | Node::Variable
+                | Node::External { .. }
+                | Node::Identity { .. }
+                | Node::Memo { .. }
+                | Node::Reshape { .. } => 0,
+                Node::Sum { children }
+                | Node::Product { children }
+                | Node::Max { children }
+                | Node::Reduce { children, .. } => children.len().saturating_sub(1),
+                // A multiply for each child, and then the sum
+                Node::SquaredNorm { children } => (2 * children.len()).saturating_sub(1),
+                // A subtraction and an exp for each child, then the sum, a ln, and an add
+                Node::LogSumExp { children } => 3 * children.len() + 1,
+                // Two multiplies and an add
+                Node::Ewma { .. } => 3,
+                Node::Bucketize { edges, .. } => edges.len(),
+                // Finding the segment, then a subtraction, a division, a multiply, and an add
+                Node::Lerp { xs, .. } => xs.len() + 4,
+                _ => 1,
+            })
+            .sum()
+    }
+
+    /// Folds over every node in topological order, which is just index order, so that custom
+    /// aggregates can be computed in one pass.
+    ///
+    /// ```
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Constant(1.5));
+    /// let b = g.push(Node::Variable);
+    /// let c = g.push(Node::Constant(2.0));
+    /// g.push(Node::Sum { children: vec![a, b, c] });
+    ///
+    /// let total = g.fold(0.0, |total, _, node| match node {
+    ///     Node::Constant(value) => total + value,
+    ///     _ => total,
+    /// });
+    /// assert_eq!(3.5, total);
+    /// ```
+    pub fn fold<B, F: FnMut(B, Idx, &Node) -> B>(&self, init: B, mut f: F) -> B {
+        self.nodes
+            .iter()
+            .enumerate()
+            .fold(init, |acc, (i, node)| f(acc, Idx(i), node))
+    }
+
+    pub fn as_subgraph(&self) -> Subgraph {
+        Subgraph {
+            indices: self.nodes.iter().enumerate().map(|(i, _)| Idx(i)).collect(),
+        }
+    }
+
+    /// Given values for each relevant variable, this computes the value for each node in the
+    /// graph.
+    pub fn evaluate_subgraph(
+        &self,
+        subgraph: Subgraph,
+        variable_to_value: impl Into<Bindings>,
+    ) -> HashMap<Idx, f64> {
+        let mut result = variable_to_value.into().into_map();
+
+        for index in subgraph.indices.iter() {
+            let value = self[*index].get_value(index, &result);
+            result.insert(*index, value);
+        }
+
+        result
+    }
+
+    pub fn evaluate(&self, variable_to_value: impl Into<Bindings>) -> HashMap<Idx, f64> {
+        self.evaluate_subgraph(self.as_subgraph(), variable_to_value)
+    }
+
+    /// Like `evaluate`, but refuses to evaluate a graph with more than `max_nodes` nodes, as a
+    /// guard against accidentally evaluating something huge. Nothing is evaluated on an error.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{EvalError, Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Constant(2.0));
+    /// let c = g.push(a * b);
+    /// let values: HashMap<_, _> = vec![(a, 3.0)].into_iter().collect();
+    ///
+    /// assert_eq!(
+    ///     Err(EvalError::BudgetExceeded { nodes: 3, max_nodes: 2 }),
+    ///     g.evaluate_bounded(values.clone(), 2)
+    /// );
+    /// assert_eq!(6.0, g.evaluate_bounded(values, 100).unwrap()[&c]);
+    /// ```
+    pub fn evaluate_bounded(
+        &self,
+        values: HashMap<Idx, f64>,
+        max_nodes: usize,
+    ) -> Result<HashMap<Idx, f64>, EvalError> {
+        if self.len() > max_nodes {
+            return Err(EvalError::BudgetExceeded {
+                nodes: self.len(),
+                max_nodes,
+            });
+        }
+        Ok(self.evaluate(values))
+    }
+
+    /// Like `evaluate`, but a variable can be missing, given as `None`. A missing value makes
+    /// every node that depends on it `None` as well, while nodes whose inputs are all present
+    /// are computed as usual.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Variable);
+    /// let two = g.push(Node::Constant(2.0));
+    /// let uses_a = g.push(a * two);
+    /// let uses_b = g.push(b * two);
+    /// let uses_both = g.push(uses_a + uses_b);
+    ///
+    /// let values: HashMap<_, _> = vec![(a, Some(1.5)), (b, None)].into_iter().collect();
+    /// let result = g.evaluate_nan_aware(values);
+    /// assert_eq!(Some(3.0), result[&uses_a]);
+    /// assert_eq!(Some(2.0), result[&two]);
+    /// assert_eq!(None, result[&uses_b]);
+    /// assert_eq!(None, result[&uses_both]);
+    /// ```
+    pub fn evaluate_nan_aware(
+        &self,
+        values: HashMap<Idx, Option<f64>>,
+    ) -> HashMap<Idx, Option<f64>> {
+        let mut present: HashMap<Idx, f64> = values
+            .iter()
+            .filter_map(|(&idx, value)| value.map(|value| (idx, value)))
+            .collect();
+        let mut result = values;
+
+        for (i, node) in self.nodes.iter().enumerate() {
+            let index = Idx(i);
+            let missing = result.get(&index) == Some(&None);
+            let value = if !missing
+                && node
+                    .children()
+                    .iter()
+                    .all(|child| present.contains_key(child))
+            {
+                let value = node.get_value(&index, &present);
+                present.insert(index, value);
+                Some(value)
+            } else {
+                None
+            };
+            result.insert(index, value);
+        }
+
+        result
+    }
+
+    /// Like `evaluate`, but also looks up the value of every `External` node by its id in
+    /// `externals`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if an `External` node's id isn't in `externals`.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let sensor = g.push(Node::External { id: 7 });
+    /// let sum = g.push(a + sensor);
+    ///
+    /// let values: HashMap<_, _> = vec![(a, 1.0)].into_iter().collect();
+    /// let externals: HashMap<_, _> = vec![(7, 0.5)].into_iter().collect();
+    /// assert_eq!(1.5, g.evaluate_with_externals(values, &externals)[&sum]);
+    /// ```
+    pub fn evaluate_with_externals(
+        &self,
+        variable_to_value: impl Into<Bindings>,
+        externals: &HashMap<usize, f64>,
+    ) -> HashMap<Idx, f64> {
+        let mut values = variable_to_value.into();
+        for (i, node) in self.nodes.iter().enumerate() {
+            if let Node::External { id } = node {
+                values.set(Idx(i), externals[id]);
+            }
+        }
+        self.evaluate(values)
+    }
+
+    /// Computes the values of just `outputs`. Everything that any of them needs is evaluated
+    /// together, exactly once, so intermediate nodes that the outputs share are reused.
+    ///
+    /// ```
+    /// use std::cell::Cell;
+    /// use std::collections::HashMap;
+    /// use std::rc::Rc;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Idx, Node};
+    ///
+    /// // A sum that counts how many times it's evaluated
+    /// fn counting_sum(children: Vec<Idx>, calls: &Rc<Cell<usize>>) -> Node {
+    ///     let calls = calls.clone();
+    ///     Node::custom(
+    ///         children,
+    ///         move |inputs| {
+    ///             calls.set(calls.get() + 1);
+    ///             inputs.iter().sum()
+    ///         },
+    ///         |inputs| vec![1.0; inputs.len()],
+    ///     )
+    /// }
+    ///
+    /// let shared_calls = Rc::new(Cell::new(0));
+    /// let unrequested_calls = Rc::new(Cell::new(0));
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Variable);
+    /// let shared = g.push(counting_sum(vec![a, b], &shared_calls));
+    /// let doubled = g.push(shared * 2.0);
+    /// let squared = g.push(Node::Pow { child: shared, exponent: 2.0 });
+    /// g.push(counting_sum(vec![squared], &unrequested_calls));
+    ///
+    /// let values: HashMap<_, _> = vec![(a, 1.0), (b, 2.0)].into_iter().collect();
+    /// let outputs = g.evaluate_outputs_subset(&[doubled, squared], values);
+    /// let expected: HashMap<_, _> = vec![(doubled, 6.0), (squared, 9.0)].into_iter().collect();
+    /// assert_eq!(expected, outputs);
+    /// assert_eq!(1, shared_calls.get());
+    /// assert_eq!(0, unrequested_calls.get());
+    /// ```
+    pub fn evaluate_outputs_subset(
+        &self,
+        outputs: &[Idx],
+        values: HashMap<Idx, f64>,
+    ) -> HashMap<Idx, f64> {
+        let all_values = self.evaluate_subgraph(self.subgraph_for(outputs), values);
+        outputs
+            .iter()
+            .map(|output| (*output, all_values[output]))
+            .collect()
+    }
+
+    /// This transforms the graph by taking the derivative. The returned subgraph contains the new
+    /// nodes along with any original nodes that they depend on.
+    ///
+    /// If `of` depends on a node that can't be differentiated, which is a partial derivative of
+    /// a custom node, this returns `GraphError::NotTwiceDifferentiable`.
+    pub fn derivative(
+        &mut self,
+        of: Idx,
+        wrt: HashSet<Idx>,
+    ) -> Result<(Idx, Subgraph), GraphError> {
+        let (of_derivative, subgraph, _) = self.derivative_map(of, wrt)?;
+        Ok((of_derivative, subgraph))
+    }
+
+    /// Like `derivative`, but also returns the derivative of every original node, mapped from
+    /// the original node to its derivative. Nodes that can't be differentiated, and the nodes
+    /// that depend on them, are left out, unless `of` is one of them, which is an error.
+    ///
+    /// ```
+    /// use std::collections::{HashMap, HashSet};
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Variable);
+    /// let product = g.push(a * b);
+    /// let exp = g.push(Node::Exp { child: product });
+    /// let original_len = g.len();
+    ///
+    /// let wrt: HashSet<_> = vec![a].into_iter().collect();
+    /// let (d_exp, subgraph, derivatives) = g.derivative_map(exp, wrt).unwrap();
+    /// assert_eq!(original_len, derivatives.len());
+    /// assert_eq!(d_exp, derivatives[&exp]);
+    ///
+    /// let values: HashMap<_, _> = vec![(a, 1.0), (b, 3.0)].into_iter().collect();
+    /// let result = g.evaluate_subgraph(subgraph, values);
+    /// assert_eq!(1.0, result[&derivatives[&a]]);
+    /// assert_eq!(0.0, result[&derivatives[&b]]);
+    /// assert_eq!(3.0, result[&derivatives[&product]]);
+    /// ```
+    pub fn derivative_map(
+        &mut self,
+        of: Idx,
+        wrt: HashSet<Idx>,
+    ) -> Result<(Idx, Subgraph, HashMap<Idx, Idx>), GraphError> {
+        // Memoize the derivative of each node
+        let mut derivatives: HashMap<Idx, Idx> = HashMap::new();
+        // The node without a gradient that stops each node from being differentiated, if any
+        let mut blocked_by: Vec<Option<Idx>> = Vec::with_capacity(self.nodes.len());
+
+        for old_index in 0..self.nodes.len() {
+            let old_index = Idx(old_index);
+            let old_node = self[old_index].clone();
+            let blocker = match old_node {
+                Node::Custom { grad: None, .. } => Some(old_index),
+                _ => old_node
+                    .children()
+                    .into_iter()
+                    .filter_map(|child| blocked_by[child.0])
+                    .next(),
+            };
+            if blocker.is_none() {
+                let new_index = old_node.derivative(old_index, &wrt, &derivatives, self);
+                derivatives.insert(old_index, new_index);
+            }
+            blocked_by.push(blocker);
+        }
+
+        if let Some(blocker) = blocked_by[of.0] {
+            return Err(GraphError::NotTwiceDifferentiable(blocker));
+        }
+        let outputs: Vec<Idx> = derivatives.values().cloned().collect();
+        let subgraph = self.subgraph_for(&outputs);
+        Ok((derivatives[&of], subgraph, derivatives))
+    }
+
+    /// Lists the nodes, in index order, that none of the `keep` outputs depend on. These are
+    /// often left over from taking derivatives.
+    ///
+    /// ```
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Variable);
+    /// let product = g.push(a * b);
+    /// let exp = g.push(Node::Exp { child: a });
+    ///
+    /// let wrt = vec![a].into_iter().collect();
+    /// let (d_product, _, derivatives) = g.derivative_map(product, wrt).unwrap();
+    /// assert_eq!(vec![derivatives[&exp]], g.dead_nodes(&[product, exp, d_product]));
+    ///
+    /// // Only the derivative of the product is wanted, and it doesn't need exp(a) or the
+    /// // product itself
+    /// let dead = g.dead_nodes(&[d_product]);
+    /// assert!(dead.contains(&product));
+    /// assert!(dead.contains(&exp));
+    /// assert!(dead.contains(&derivatives[&exp]));
+    /// assert!(!dead.contains(&derivatives[&a]));
+    /// assert!(!dead.contains(&derivatives[&b]));
+    /// ```
+    pub fn dead_nodes(&self, keep: &[Idx]) -> Vec<Idx> {
+        let live: HashSet<Idx> = self.subgraph_for(keep).indices().iter().cloned().collect();
+        (0..self.len())
+            .map(Idx)
+            .filter(|idx| !live.contains(idx))
+            .collect()
+    }
+
+    /// Like `derivative_pruned`, but leaves this graph alone and writes the derivative of `of`
+    /// into `target` instead. Any original nodes that the derivative depends on are copied over
+    /// too, in index order, so variables keep their relative order in `target`.
+    ///
+    /// ```
+    /// use std::collections::{HashMap, HashSet};
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Variable);
+    /// let product = g.push(a * b);
+    ///
+    /// let mut target = Graph::default();
+    /// let wrt: HashSet<_> = vec![a].into_iter().collect();
+    /// let d_product = g.derivative_into(product, &wrt, &mut target).unwrap();
+    /// assert_eq!(3, g.len());
+    ///
+    /// // d(a * b) / da = b, which is the only variable that got copied
+    /// let b_copy = target.leaves().find(|&i| target[i] == Node::Variable).unwrap();
+    /// let values: HashMap<_, _> = vec![(b_copy, 5.0)].into_iter().collect();
+    /// assert_eq!(5.0, target.evaluate(values)[&d_product]);
+    /// ```
+    pub fn derivative_into(
+        &self,
+        of: Idx,
+        wrt: &HashSet<Idx>,
+        target: &mut Graph,
+    ) -> Result<Idx, GraphError> {
+        let mut scratch = self.clone();
+        let (of_derivative, subgraph) = scratch.derivative_pruned(of, wrt.clone())?;
+
+        let mut copies: HashMap<Idx, Idx> = HashMap::new();
+        for index in subgraph.indices() {
+            let node = scratch[*index].map_children(|child| copies[&child]);
+            copies.insert(*index, target.push(node));
+        }
+        Ok(copies[&of_derivative])
+    }
+
+    /// Like `derivative`, but never emits nodes for derivatives that are known to be zero.
+    /// Sums and products drop the terms that would be zero, and a node whose children all have
+    /// zero derivatives has a zero derivative itself. The returned subgraph only contains what's
+    /// needed to compute the derivative of `of`, so it's much smaller. Only the nodes that `of`
+    /// depends on are differentiated, so this only fails if one of those can't be.
+    ///
+    /// ```
+    /// use std::collections::{HashMap, HashSet};
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let two = g.push(Node::Constant(2.0));
+    /// let sum = g.push(a + two);
+    ///
+    /// let wrt: HashSet<_> = vec![a].into_iter().collect();
+    /// let (d_sum, subgraph) = g.derivative_pruned(sum, wrt).unwrap();
+    /// assert_eq!(&[d_sum], subgraph.indices());
+    /// assert_eq!(Node::Constant(1.0), g[d_sum]);
+    ///
+    /// // The values still agree with the full derivative
+    /// let b = g.push(Node::Variable);
+    /// let product = g.push(Node::Product { children: vec![a, b, sum] });
+    /// let values: HashMap<_, _> = vec![(a, 3.0), (b, 4.0)].into_iter().collect();
+    /// let wrt: HashSet<_> = vec![a].into_iter().collect();
+    /// let (d_full, full) = g.derivative(product, wrt.clone()).unwrap();
+    /// let (d_pruned, pruned) = g.derivative_pruned(product, wrt).unwrap();
+    /// assert!(pruned.indices().len() < full.indices().len());
+    /// assert_eq!(
+    ///     g.evaluate_subgraph(full, values.clone())[&d_full],
+    ///     g.evaluate_subgraph(pruned, values)[&d_pruned]
+    /// );
+    /// ```
+    pub fn derivative_pruned(
+        &mut self,
+        of: Idx,
+        wrt: HashSet<Idx>,
+    ) -> Result<(Idx, Subgraph), GraphError> {
+        // None means that the derivative is zero. Nodes that of doesn't depend on are skipped.
+        let mut derivatives: Vec<Option<Idx>> = vec![None; self.nodes.len()];
+        // Some nodes still need a zero to refer to, so create at most one
+        let mut zero = None;
+
+        for &old_index in self.subgraph_for(&[of]).indices() {
+            let old_node = self[old_index].clone();
+            let child_derivatives: Vec<Option<Idx>> = old_node
+                .children()
+                .into_iter()
+                .map(|child| derivatives[child.0])
+                .collect();
+
+            let new_index = match old_node {
+                Node::Constant(_) | Node::VectorConstant(_) => None,
+                Node::Variable | Node::External { .. } => {
+                    if wrt.contains(&old_index) {
+                        Some(self.push(Node::Constant(1.0)))
+                    } else {
+                        None
+                    }
+                }
+                Node::Sum { .. }
+                | Node::Reduce {
+                    op: ReduceOp::Sum, ..
+                } => {
+                    let terms: Vec<Idx> = child_derivatives.into_iter().flatten().collect();
+                    match terms.len() {
+                        0 => None,
+                        1 => Some(terms[0]),
+                        _ => Some(self.push(Node::Sum { children: terms })),
+                    }
+                }
+                Node::Product { ref children }
+                | Node::Reduce {
+                    op: ReduceOp::Product,
+                    ref children,
+                } => {
+                    let terms = reduce::product_rule_terms(children, &child_derivatives, self);
+                    match terms.len() {
+                        0 => None,
+                        1 => Some(terms[0]),
+                        _ => Some(self.push(Node::Sum { children: terms })),
+                    }
+                }
+                _ if child_derivatives.iter().all(Option::is_none) => None,
+                Node::Custom { grad: None, .. } => {
+                    return Err(GraphError::NotTwiceDifferentiable(old_index))
+                }
+                _ => {
+                    let mut memo = HashMap::new();
+                    for (child, derivative) in
+                        old_node.children().into_iter().zip(child_derivatives)
+                    {
+                        let derivative = derivative.unwrap_or_else(|| {
+                            *zero.get_or_insert_with(|| self.push(Node::Constant(0.0)))
+                        });
+                        memo.insert(child, derivative);
+                    }
+                    Some(old_node.derivative(old_index, &wrt, &memo, self))
+                }
+            };
+            derivatives[old_index.0] = new_index;
+        }
+
+        let of_derivative = match derivatives[of.0] {
+            Some(derivative) => derivative,
+            None => self.push(Node::Constant(0.0)),
+        };
+        Ok((of_derivative, self.subgraph_for(&[of_derivative])))
+    }
+
+    /// The smallest subgraph that contains `outputs` and everything needed to compute them.
+    pub fn subgraph_for(&self, outputs: &[Idx]) -> Subgraph {
+        let mut needed: HashSet<Idx> = outputs.iter().cloned().collect();
+        let mut stack = outputs.to_vec();
+
+        while let Some(index) = stack.pop() {
+            for child in self[index].children() {
+                if needed.insert(child) {
+                    stack.push(child);
+                }
+            }
+        }
+
+        Subgraph::new(needed.into_iter())
+    }
+
+    /// Builds the softmax cross-entropy loss of `logits` against `target_class`, which is
+    /// `logsumexp(logits) - logits[target_class]`, and returns the index of the loss.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `target_class` is not a valid index into `logits`.
+    ///
+    /// ```
+    /// use std::collections::{HashMap, HashSet};
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let logits: Vec<_> = (0..3).map(|_| g.push(Node::Variable)).collect();
+    /// let loss = g.push_cross_entropy(&logits, 1);
+    /// let values = |x: &[f64]| -> HashMap<_, _> {
+    ///     logits.iter().cloned().zip(x.iter().cloned()).collect()
+    /// };
+    ///
+    /// // Raising the correct logit lowers the loss
+    /// let before = g.evaluate(values(&[1.0, 2.0, 3.0]))[&loss];
+    /// let after = g.evaluate(values(&[1.0, 3.0, 3.0]))[&loss];
+    /// assert!(after < before);
+    ///
+    /// // The gradient is softmax - one_hot
+    /// let at = values(&[1.0, 2.0, 3.0]);
+    /// let total: f64 = [1f64, 2.0, 3.0].iter().map(|x| x.exp()).sum();
+    /// for (i, &logit) in logits.iter().enumerate() {
+    ///     let softmax = ((i + 1) as f64).exp() / total;
+    ///     let one_hot = if i == 1 { 1.0 } else { 0.0 };
+    ///     let wrt: HashSet<_> = vec![logit].into_iter().collect();
+    ///     let (d_loss, subgraph) = g.derivative(loss, wrt).unwrap();
+    ///     let gradient = g.evaluate_subgraph(subgraph, at.clone())[&d_loss];
+    ///     assert!((softmax - one_hot - gradient).abs() < 1e-12);
+    /// }
+    /// ```
+    pub fn push_cross_entropy(&mut self, logits: &[Idx], target_class: usize) -> Idx {
+        assert!(
+            target_class < logits.len(),
+            "target class {} is out of range for {} logits",
+            target_class,
+            logits.len()
+        );
+
+        let lse = self.push(Node::LogSumExp {
+            children: logits.to_vec(),
+        });
+        let minus_one = self.push(Node::Constant(-1.0));
+        let minus_target = self.push(minus_one * logits[target_class]);
+        self.push(lse + minus_target)
+    }
+
+    /// Returns true if the node provably evaluates to zero for every input. This only looks at
+    /// the structure of the graph, so it can return false for nodes that happen to always be
+    /// zero.
+    ///
+    /// ```
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let zero = g.push(Node::Constant(0.0));
+    /// let one = g.push(Node::Constant(1.0));
+    /// let x = g.push(Node::Variable);
+    /// let zero_product = g.push(x * zero);
+    /// let sum = g.push(one + zero_product);
+    /// let zero_mod_one = g.push(Node::Modulo { a: zero, b: one });
+    /// let zero_mod_x = g.push(Node::Modulo { a: zero, b: x });
+    ///
+    /// assert!(g.is_always_zero(zero));
+    /// assert!(g.is_always_zero(zero_product));
+    /// assert!(!g.is_always_zero(sum));
+    /// assert!(!g.is_always_zero(x));
+    /// assert!(g.is_always_zero(zero_mod_one));
+    /// // x could be zero, and 0 rem_euclid 0 is NaN
+    /// assert!(!g.is_always_zero(zero_mod_x));
+    /// ```
+    pub fn is_always_zero(&self, idx: Idx) -> bool {
+        self.zero_flags(idx)[idx.0]
+    }
+
+    /// Whether each node up to and including `upto` is provably zero, as in `is_always_zero`.
+    fn zero_flags(&self, upto: Idx) -> Vec<bool> {
+        // Children come first, so one pass over the prefix of the graph is enough
+        let mut is_zero: Vec<bool> = Vec::with_capacity(upto.0 + 1);
+
+        for node in &self.nodes[..=upto.0] {
+            let zero = match node {
+                Node::Constant(value) => *value == 0.0,
+                Node::VectorConstant(values) => values.iter().all(|value| *value == 0.0),
+                Node::Variable
+                | Node::External { .. }
+                | Node::Exp { .. }
+                | Node::Ln { .. }
+                | Node::LogSumExp { .. }
+                | Node::Softplus { .. }
+                | Node::Cosh { .. }
+                | Node::Greater { .. }
+                | Node::Equal { .. }
+                | Node::Bucketize { .. }
+                // 0^0 is 1
+                | Node::PowNode { .. }
+                | Node::Custom { .. } => false,
+                Node::Tanh { child }
+                | Node::Gelu { child }
+                | Node::Sign { child }
+                | Node::Normalize { child }
+                | Node::Identity { child }
+                | Node::Memo { child }
+                | Node::Reshape { child, .. }
+                | Node::Sinh { child }
+                | Node::Atan { child }
+                | Node::Asin { child } => is_zero[child.0],
+                Node::Scale { child, factor } => is_zero[child.0] || *factor == 0.0,
+                Node::Pow { child, exponent } => is_zero[child.0] && *exponent > 0.0,
+                Node::MatMul { a, b } => is_zero[a.0] || is_zero[b.0],
+                Node::Select {
+                    if_true, if_false, ..
+                } => is_zero[if_true.0] && is_zero[if_false.0],
+                // 0 rem_euclid 0 is NaN, so the divisor has to be provably non-zero too
+                Node::Modulo { a, b } => {
+                    is_zero[a.0]
+                        && match self.nodes[b.0] {
+                            Node::Constant(divisor) => divisor != 0.0 && !divisor.is_nan(),
+                            _ => false,
+                        }
+                }
+                Node::SafeDiv {
+                    numerator, default, ..
+                } => is_zero[numerator.0] && *default == 0.0,
+                Node::Ewma { input, prev, .. } => is_zero[input.0] && is_zero[prev.0],
+                Node::Lerp { ys, .. } => ys.iter().all(|&y| y == 0.0),
+                Node::Sum { children } | Node::SquaredNorm { children } => {
+                    ReduceOp::Sum.is_zero(children.iter().map(|child| is_zero[child.0]))
+                }
+                Node::Product { children } => {
+                    ReduceOp::Product.is_zero(children.iter().map(|child| is_zero[child.0]))
+                }
+                Node::Max { children } => {
+                    ReduceOp::Max.is_zero(children.iter().map(|child| is_zero[child.0]))
+                }
+                Node::Reduce { op, children } => {
+                    op.is_zero(children.iter().map(|child| is_zero[child.0]))
+                }
+            };
+            is_zero.push(zero);
+        }
+
+        is_zero
+    }
+
+    /// Evaluates everything that `of` depends on, then walks back through those nodes to find
+    /// the gradient of `of` with respect to each of them. The forward values are computed once
+    /// and reused by the reverse pass.
+    ///
+    /// Returns the values and the gradients. Nodes that `of` doesn't depend on are left out of
+    /// both.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// // d = a * b + exp(a)
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Variable);
+    /// let c = g.push(a * b);
+    /// let exp_a = g.push(Node::Exp { child: a });
+    /// let d = g.push(c + exp_a);
+    ///
+    /// let values: HashMap<_, _> = vec![(a, 0.5), (b, 3.0)].into_iter().collect();
+    /// let (forward, gradients) = g.forward_backward(d, values.clone()).unwrap();
+    /// assert_eq!(1.5 + 0.5f64.exp(), forward[&d]);
+    /// for &wrt in &[a, b] {
+    ///     let numerical = g.numerical_gradient(d, wrt, &values);
+    ///     assert!((numerical - gradients[&wrt]).abs() < 1e-6);
+    /// }
+    /// ```
+    pub fn forward_backward(
+        &self,
+        of: Idx,
+        values: impl Into<Bindings>,
+    ) -> Result<ValuesAndGradients, GraphError> {
+        self.forward_backward_with_eps(of, values, 0.0)
+    }
+
+    /// The gradients from `forward_backward`, except that `epsilon` is added to the denominator
+    /// whenever a derivative divides by an input, which is currently just `Ln`. This keeps
+    /// gradients finite near zero at the cost of accuracy: the derivative of `ln(x)` becomes
+    /// `1 / (x + epsilon)`, which is at most `1 / epsilon` at zero and is off by a relative
+    /// error of about `epsilon / x` when `x` is much bigger than `epsilon`.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let x = g.push(Node::Variable);
+    /// let ln = g.push(Node::Ln { child: x });
+    /// let values: HashMap<_, _> = vec![(x, 0.0)].into_iter().collect();
+    ///
+    /// let (_, gradients) = g.forward_backward(ln, values.clone()).unwrap();
+    /// assert!(gradients[&x].is_infinite());
+    ///
+    /// let gradients = g.gradients_with_eps(ln, values, 1e-9).unwrap();
+    /// assert!(gradients[&x].is_finite());
+    /// assert!(gradients[&x] > 1e8);
+    /// ```
+    pub fn gradients_with_eps(
+        &self,
+        of: Idx,
+        values: impl Into<Bindings>,
+        epsilon: f64,
+    ) -> Result<HashMap<Idx, f64>, GraphError> {
+        Ok(self.forward_backward_with_eps(of, values, epsilon)?.1)
+    }
+
+    fn forward_backward_with_eps(
+        &self,
+        of: Idx,
+        values: impl Into<Bindings>,
+        epsilon: f64,
+    ) -> Result<ValuesAndGradients, GraphError> {
+        let mut seeds = HashMap::new();
+        seeds.insert(of, 1.0);
+        self.forward_backward_seeded(seeds, values, epsilon)
+    }
+
+    /// Reverse mode starting from the gradient of each node in `seeds`, rather than from a
+    /// single output with a gradient of one.
+    fn forward_backward_seeded(
+        &self,
+        seeds: HashMap<Idx, f64>,
+        values: impl Into<Bindings>,
+        epsilon: f64,
+    ) -> Result<ValuesAndGradients, GraphError> {
+        let outputs: Vec<Idx> = seeds.keys().cloned().collect();
+        let subgraph = self.subgraph_for(&outputs);
+        self.check_differentiable(&subgraph)?;
+        let values = self.evaluate_subgraph(subgraph.clone(), values);
+
+        let mut gradients = seeds;
+
+        for index in subgraph.indices.iter().rev() {
+            let adjoint = gradients.get(index).cloned().unwrap_or(0.0);
+            let node = &self[*index];
+            let partials = node.local_gradients_with_eps(index, &values, epsilon);
+            for (child, partial) in node.children().into_iter().zip(partials) {
+                *gradients.entry(child).or_insert(0.0) += adjoint * partial;
+            }
+        }
+
+        Ok((values, gradients))
+    }
+
+    /// Returns an error if anything in `subgraph` is a custom node without a gradient, since
+    /// reverse and forward mode need the local gradients of every node.
+    pub(crate) fn check_differentiable(&self, subgraph: &Subgraph) -> Result<(), GraphError> {
+        match subgraph
+            .indices()
+            .iter()
+            .find(|&&idx| matches!(self[idx], Node::Custom { grad: None, .. }))
+        {
+            Some(&idx) => Err(GraphError::NotTwiceDifferentiable(idx)),
+            None => Ok(()),
+        }
+    }
+
+    /// The partial derivative of `of` with respect to every `Variable` in the graph, sorted by
+    /// index. Variables that `of` doesn't depend on get a gradient of zero.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// // a * b + a
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Variable);
+    /// let product = g.push(a * b);
+    /// let sum = g.push(product + a);
+    ///
+    /// let values: HashMap<_, _> = vec![(a, 2.0), (b, 5.0)].into_iter().collect();
+    /// assert_eq!(vec![(a, 6.0), (b, 2.0)], g.gradient_vector(sum, &values).unwrap());
+    /// ```
+    pub fn gradient_vector(
+        &self,
+        of: Idx,
+        values: &HashMap<Idx, f64>,
+    ) -> Result<Vec<(Idx, f64)>, GraphError> {
+        let (_, gradients) = self.forward_backward(of, values.clone())?;
+
+        Ok(self
+            .nodes
+            .iter()
+            .enumerate()
+            .filter(|&(_, node)| *node == Node::Variable)
+            .map(|(i, _)| (Idx(i), gradients.get(&Idx(i)).cloned().unwrap_or(0.0)))
+            .collect())
+    }
+
+    /// The L2 norm of `gradient_vector`, which optimizers can use to check for convergence.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// // (x - 3)^2, which is stationary at x = 3
+    /// let mut g = Graph::default();
+    /// let x = g.push(Node::Variable);
+    /// let minus_three = g.push(Node::Constant(-3.0));
+    /// let shifted = g.push(x + minus_three);
+    /// let square = g.push(Node::Pow { child: shifted, exponent: 2.0 });
+    ///
+    /// let at = |x_value: f64| -> HashMap<_, _> { vec![(x, x_value)].into_iter().collect() };
+    /// assert_eq!(0.0, g.gradient_norm(square, &at(3.0)).unwrap());
+    /// assert_eq!(4.0, g.gradient_norm(square, &at(1.0)).unwrap());
+    /// ```
+    pub fn gradient_norm(&self, of: Idx, values: &HashMap<Idx, f64>) -> Result<f64, GraphError> {
+        Ok(self
+            .gradient_vector(of, values)?
+            .iter()
+            .map(|(_, gradient)| gradient * gradient)
+            .sum::<f64>()
+            .sqrt())
+    }
+
+    /// `gradient_vector` sorted so that the variables with the largest partial derivatives, in
+    /// absolute value, come first. These are the inputs that `of` is most sensitive to at this
+    /// point. Ties keep their index order.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// // a - 5 * b
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Variable);
+    /// let scaled = g.push(b * -5.0);
+    /// let output = g.push(a + scaled);
+    ///
+    /// let values: HashMap<_, _> = vec![(a, 1.0), (b, 1.0)].into_iter().collect();
+    /// assert_eq!(vec![(b, -5.0), (a, 1.0)], g.sensitivity_ranking(output, &values).unwrap());
+    /// ```
+    pub fn sensitivity_ranking(
+        &self,
+        of: Idx,
+        at: &HashMap<Idx, f64>,
+    ) -> Result<Vec<(Idx, f64)>, GraphError> {
+        let mut ranking = self.gradient_vector(of, at)?;
+        ranking.sort_by(|(_, x), (_, y)| {
+            y.abs()
+                .partial_cmp(&x.abs())
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        Ok(ranking)
+    }
+
+    /// The derivative of `of` with respect to any node, holding that node's children fixed.
+    /// This is the adjoint that reverse mode computes on the way to the variables, so `wrt`
+    /// doesn't have to be a variable. It's zero if `of` doesn't depend on `wrt`.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// // output = (a + b) * b
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Variable);
+    /// let sum = g.push(a + b);
+    /// let output = g.push(sum * b);
+    ///
+    /// let values: HashMap<_, _> = vec![(a, 2.0), (b, 3.0)].into_iter().collect();
+    /// assert_eq!(3.0, g.gradient_wrt(output, sum, &values).unwrap());
+    /// assert_eq!(8.0, g.gradient_wrt(output, b, &values).unwrap());
+    /// assert_eq!(0.0, g.gradient_wrt(sum, output, &values).unwrap());
+    /// ```
+    pub fn gradient_wrt(
+        &self,
+        of: Idx,
+        wrt: Idx,
+        values: &HashMap<Idx, f64>,
+    ) -> Result<f64, GraphError> {
+        let (_, gradients) = self.forward_backward(of, values.clone())?;
+        Ok(gradients.get(&wrt).cloned().unwrap_or(0.0))
+    }
+
+    /// Applies the chain rule across a graph boundary. `upstream` holds the gradient of some
+    /// loss with respect to each output of this graph, as computed by whatever the outputs
+    /// feed into, and this returns the gradient of that loss with respect to each node in
+    /// `wrt`. Outputs can be any nodes, and more than one output can be given at once.
+    ///
+    /// ```
+    /// use std::collections::{HashMap, HashSet};
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// // y = a * b
+    /// let mut first = Graph::default();
+    /// let a = first.push(Node::Variable);
+    /// let b = first.push(Node::Variable);
+    /// let y = first.push(a * b);
+    ///
+    /// // loss = x^2, where x is y
+    /// let mut second = Graph::default();
+    /// let x = second.push(Node::Variable);
+    /// let loss = second.push(x * x);
+    ///
+    /// let first_values: HashMap<_, _> = vec![(a, 2.0), (b, 3.0)].into_iter().collect();
+    /// let y_value = first.evaluate(first_values.clone())[&y];
+    /// let second_values: HashMap<_, _> = vec![(x, y_value)].into_iter().collect();
+    /// let d_loss_d_y = second.gradient_wrt(loss, x, &second_values).unwrap();
+    ///
+    /// let upstream: HashMap<_, _> = vec![(y, d_loss_d_y)].into_iter().collect();
+    /// let wrt: HashSet<_> = vec![a, b].into_iter().collect();
+    /// let gradients = first.chain_derivative(&upstream, first_values, &wrt).unwrap();
+    /// // loss = (a * b)^2, so d/da = 2 * a * b^2 and d/db = 2 * a^2 * b
+    /// assert_eq!(36.0, gradients[&a]);
+    /// assert_eq!(24.0, gradients[&b]);
+    /// ```
+    pub fn chain_derivative(
+        &self,
+        upstream: &HashMap<Idx, f64>,
+        values: impl Into<Bindings>,
+        wrt: &HashSet<Idx>,
+    ) -> Result<HashMap<Idx, f64>, GraphError> {
+        let (_, gradients) = self.forward_backward_seeded(upstream.clone(), values, 0.0)?;
+        Ok(wrt
+            .iter()
+            .map(|&idx| (idx, gradients.get(&idx).cloned().unwrap_or(0.0)))
+            .collect())
+    }
+
+    /// Estimates the derivative of `of` with respect to the variable `wrt` using central finite
+    /// differences.
+    /// This is slow and imprecise, but it's useful for checking the other ways of taking
+    /// derivatives.
+    pub fn numerical_gradient(&self, of: Idx, wrt: Idx, values: &HashMap<Idx, f64>) -> f64 {
+        let step = 1e-6;
+        let subgraph = self.subgraph_for(&[of]);
+        let evaluate_at = |offset: f64| {
+            let mut values = values.clone();
+            *values.entry(wrt).or_insert(0.0) += offset;
+            self.evaluate_subgraph(subgraph.clone(), values)[&of]
+        };
+
+        (evaluate_at(step) - evaluate_at(-step)) / (2.0 * step)
+    }
+
+    /// Checks `derivative` against `numerical_gradient` at each of `samples`, returning true if
+    /// they're all within `tol` of each other. The derivative nodes are pushed once and stay in
+    /// the graph.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let square = g.push(a * a);
+    ///
+    /// let samples: Vec<HashMap<_, _>> = [-3.0, -0.5, 0.0, 1.0, 10.0]
+    ///     .iter()
+    ///     .map(|&value| vec![(a, value)].into_iter().collect())
+    ///     .collect();
+    /// assert!(g.verify_derivative(square, a, &samples, 1e-6).unwrap());
+    /// ```
+    pub fn verify_derivative(
+        &mut self,
+        of: Idx,
+        wrt: Idx,
+        samples: &[HashMap<Idx, f64>],
+        tol: f64,
+    ) -> Result<bool, GraphError> {
+        let wrt_set: HashSet<Idx> = vec![wrt].into_iter().collect();
+        let (derivative, subgraph) = self.derivative(of, wrt_set)?;
+
+        Ok(samples.iter().all(|sample| {
+            let analytic = self.evaluate_subgraph(subgraph.clone(), sample.clone())[&derivative];
+            let numerical = self.numerical_gradient(of, wrt, sample);
+            (analytic - numerical).abs() <= tol
+        }))
+    }
+
+    /// Builds GELU out of `Tanh`, `Pow`, `Product`, and `Sum` nodes, returning the output. This
+    /// computes the same thing as `Node::Gelu`.
+    pub fn push_gelu(&mut self, x: Idx) -> Idx {
+        let cubed = self.push(Node::Pow {
+            child: x,
+            exponent: 3.0,
+        });
+        let cubic = self.push(Node::Constant(GELU_CUBIC));
+        let cubic_term = self.push(cubic * cubed);
+        let inner = self.push(x + cubic_term);
+        let scale = self.push(Node::Constant(GELU_SCALE));
+        let scaled = self.push(scale * inner);
+        let tanh = self.push(Node::Tanh { child: scaled });
+        let one = self.push(Node::Constant(1.0));
+        let gate = self.push(one + tanh);
+        let half = self.push(Node::Constant(0.5));
+        self.push(Node::Product {
+            children: vec![half, x, gate],
+        })
+    }
+
+    /// Takes the max of `inputs` using a balanced tree of two-input `Max` nodes, returning the
+    /// root. Compared to folding from the left, this keeps the depth of the graph logarithmic in
+    /// the number of inputs.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `inputs` is empty.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Idx, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let inputs: Vec<_> = [3.0, 1.0, 4.0, 1.0, 5.0]
+    ///     .iter()
+    ///     .map(|&value| g.push(Node::Constant(value)))
+    ///     .collect();
+    /// let max = g.push_max_reduce(&inputs);
+    /// assert_eq!(5.0, g.evaluate(HashMap::<Idx, f64>::new())[&max]);
+    ///
+    /// fn depth(g: &Graph, idx: Idx) -> usize {
+    ///     g[idx].children().into_iter().map(|child| 1 + depth(g, child)).max().unwrap_or(0)
+    /// }
+    /// // ceil(log2(5)) = 3
+    /// assert_eq!(3, depth(&g, max));
+    /// ```
+    pub fn push_max_reduce(&mut self, inputs: &[Idx]) -> Idx {
+        assert!(!inputs.is_empty(), "can't take the max of no inputs");
+
+        let mut level = inputs.to_vec();
+        while level.len() > 1 {
+            level = level
+                .chunks(2)
+                .map(|pair| {
+                    if pair.len() == 2 {
+                        self.push(Node::Max {
+                            children: pair.to_vec(),
+                        })
+                    } else {
+                        pair[0]
+                    }
+                })
+                .collect();
+        }
+
+        level[0]
+    }
+
+    /// Builds the running sums of `inputs`. Each sum adds one input to the previous sum, so it
+    /// only takes one new node per input. The first sum is just the first input.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Idx, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let inputs: Vec<_> = [1.0, 2.0, 3.0]
+    ///     .iter()
+    ///     .map(|&value| g.push(Node::Constant(value)))
+    ///     .collect();
+    /// let sums = g.push_cumsum(&inputs);
+    ///
+    /// let values = g.evaluate(HashMap::<Idx, f64>::new());
+    /// let sum_values: Vec<f64> = sums.iter().map(|sum| values[sum]).collect();
+    /// assert_eq!(vec![1.0, 3.0, 6.0], sum_values);
+    /// assert_eq!(5, g.len());
+    /// ```
+    pub fn push_cumsum(&mut self, inputs: &[Idx]) -> Vec<Idx> {
+        let mut sums: Vec<Idx> = Vec::with_capacity(inputs.len());
+        for &input in inputs {
+            let sum = match sums.last() {
+                Some(&previous) => self.push(previous + input),
+                None => input,
+            };
+            sums.push(sum);
+        }
+        sums
+    }
+
+    /// Pushes a `Bucketize` node, after checking that `edges` are sorted.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `edges` are not sorted in increasing order.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let x = g.push(Node::Variable);
+    /// let bucket = g.push_bucketize(x, vec![0.0, 1.0, 10.0]);
+    /// let at = |x_value: f64| -> HashMap<_, _> { vec![(x, x_value)].into_iter().collect() };
+    ///
+    /// assert_eq!(0.0, g.evaluate(at(-5.0))[&bucket]);
+    /// assert_eq!(1.0, g.evaluate(at(0.0))[&bucket]);
+    /// assert_eq!(2.0, g.evaluate(at(3.0))[&bucket]);
+    /// assert_eq!(3.0, g.evaluate(at(10.0))[&bucket]);
+    /// assert_eq!(vec![(x, 0.0)], g.gradient_vector(bucket, &at(3.0)).unwrap());
+    /// ```
+    pub fn push_bucketize(&mut self, child: Idx, edges: Vec<f64>) -> Idx {
+        assert!(is_sorted(&edges), "bucketize edges must be sorted");
+        self.push(Node::Bucketize { child, edges })
+    }
+
+    /// Pushes a `Lerp` node, after checking that the table is valid.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `xs` is empty, if it is a different length from `ys`, or if it is not sorted
+    /// in increasing order.
+    pub fn push_lerp(&mut self, child: Idx, xs: Vec<f64>, ys: Vec<f64>) -> Idx {
+        assert!(!xs.is_empty(), "lerp needs at least one point");
+        assert_eq!(xs.len(), ys.len(), "lerp needs as many xs as ys");
+        assert!(is_sorted(&xs), "lerp xs must be sorted");
+        self.push(Node::Lerp { child, xs, ys })
+    }
+
+    /// Pushes the nodes for `(input - mean) / std`, which standardizes an input that has the
+    /// given mean and standard deviation, returning the index of the last one. There's no
+    /// difference node, so the subtraction is a `Sum` with a `Constant(-mean)`, and the division
+    /// is a `Scale` by `1 / std`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `mean` isn't finite, or if `std` is zero or isn't finite, since any of those
+    /// would make every output NaN or infinite.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let data = [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0];
+    /// let mut g = Graph::default();
+    /// let x = g.push(Node::Variable);
+    /// let standardized = g.push_standardize(x, 5.0, 2.0);
+    ///
+    /// let at = |x_value: f64| -> HashMap<_, _> { vec![(x, x_value)].into_iter().collect() };
+    /// let outputs: Vec<f64> = data.iter().map(|&d| g.evaluate(at(d))[&standardized]).collect();
+    /// let mean = outputs.iter().sum::<f64>() / outputs.len() as f64;
+    /// let variance = outputs.iter().map(|o| (o - mean) * (o - mean)).sum::<f64>() / 8.0;
+    /// assert_eq!(0.0, mean);
+    /// assert_eq!(1.0, variance);
+    /// assert_eq!(vec![(x, 0.5)], g.gradient_vector(standardized, &at(3.0)).unwrap());
+    /// ```
+    ///
+    /// ```should_panic
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let x = g.push(Node::Variable);
+    /// // Panics: a NaN standard deviation would make every output NaN
+    /// g.push_standardize(x, 0.0, std::f64::NAN);
+    /// ```
+    pub fn push_standardize(&mut self, input: Idx, mean: f64, std: f64) -> Idx {
+        assert!(mean.is_finite(), "the mean must be finite");
+        assert!(
+            std.is_finite() && std != 0.0,
+            "the standard deviation must be finite and non-zero"
+        );
+        let offset = self.push(Node::Constant(-mean));
+        let centered = self.push(input + offset);
+        self.push(centered * (1.0 / std))
+    }
+
+    /// Returns true if `descendant` can be reached from `ancestor` by following children, i.e.
+    /// the value of `ancestor` depends on the value of `descendant`. A node is not its own
+    /// ancestor.
+    ///
+    /// Since children always come before their parents, the search never needs to look at nodes
+    /// with an index smaller than `descendant`.
+    pub fn is_ancestor(&self, ancestor: Idx, descendant: Idx) -> bool {
+        let mut visited = HashSet::new();
+        let mut stack = vec![ancestor];
+
+        while let Some(index) = stack.pop() {
+            for child in self[index].children() {
+                if child == descendant {
+                    return true;
+                }
+                if child.0 > descendant.0 && visited.insert(child) {
+                    stack.push(child);
+                }
+            }
+        }
+
+        false
+    }
+
+    /// Returns true when neither node is an ancestor of the other, which means that the two
+    /// could be evaluated concurrently.
+    ///
+    /// ```
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Variable);
+    /// let c = g.push(a + b);
+    ///
+    /// // Parent and child depend on each other
+    /// assert!(!g.are_independent(c, a));
+    /// assert!(!g.are_independent(a, c));
+    ///
+    /// // Sibling leaves don't
+    /// assert!(g.are_independent(a, b));
+    /// ```
+    pub fn are_independent(&self, a: Idx, b: Idx) -> bool {
+        a != b && !self.is_ancestor(a, b) && !self.is_ancestor(b, a)
+    }
+
+    /// The variables that `output` depends on, which are the only parameters that an optimizer
+    /// needs to update for it.
+    ///
+    /// ```
+    /// use std::collections::HashSet;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Variable);
+    /// let two = g.push(Node::Constant(2.0));
+    /// let output = g.push(a * two);
+    /// g.push(b + output);
+    ///
+    /// let expected: HashSet<_> = vec![a].into_iter().collect();
+    /// assert_eq!(expected, g.relevant_variables(output));
+    /// ```
+    pub fn relevant_variables(&self, output: Idx) -> HashSet<Idx> {
+        self.subgraph_for(&[output])
+            .indices
+            .into_iter()
+            .filter(|&index| self[index] == Node::Variable)
+            .collect()
+    }
+
+    /// The variables whose derivative of `of` isn't structurally zero. This is like
+    /// `relevant_variables`, except that it skips paths that the gradient can't flow along: the
+    /// other factors of a product with an always-zero factor, a scale by zero, the conditions
+    /// of selects, the divisors of modulos, and anything under a comparison or a bucketize.
+    ///
+    /// ```
+    /// use std::collections::HashSet;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// // a + b * 0
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Variable);
+    /// let zero = g.push(Node::Constant(0.0));
+    /// let product = g.push(b * zero);
+    /// let output = g.push(a + product);
+    ///
+    /// let expected: HashSet<_> = vec![a].into_iter().collect();
+    /// assert_eq!(expected, g.gradient_sparsity(output));
+    /// assert_eq!(2, g.relevant_variables(output).len());
+    /// ```
+    pub fn gradient_sparsity(&self, of: Idx) -> HashSet<Idx> {
+        let is_zero = self.zero_flags(of);
+        let mut reached = vec![false; of.0 + 1];
+        reached[of.0] = true;
+
+        // Parents come after their children, so walking backwards visits each parent first
+        for i in (0..=of.0).rev() {
+            if !reached[i] {
+                continue;
+            }
+            let flowing: Vec<Idx> = match &self.nodes[i] {
+                Node::Product { children }
+                | Node::Reduce {
+                    op: ReduceOp::Product,
+                    children,
+                } => {
+                    // A child's gradient is the product of the others, so it's zero if any
+                    // other child is zero
+                    let zeros = children.iter().filter(|child| is_zero[child.0]).count();
+                    children
+                        .iter()
+                        .filter(|child| zeros - is_zero[child.0] as usize == 0)
+                        .cloned()
+                        .collect()
+                }
+                Node::Scale { factor, .. } if *factor == 0.0 => vec![],
+                Node::Select {
+                    if_true, if_false, ..
+                } => vec![*if_true, *if_false],
+                Node::Modulo { a, .. } => vec![*a],
+                Node::Greater { .. }
+                | Node::Equal { .. }
+                | Node::Bucketize { .. }
+                | Node::Sign { .. } => vec![],
+                node => node.children(),
+            };
+            for child in flowing {
+                reached[child.0] = true;
+            }
+        }
+
+        (0..=of.0)
+            .filter(|&i| reached[i] && self.nodes[i] == Node::Variable)
+            .map(Idx)
+            .collect()
+    }
+
+    /// Returns the value of `idx` if it only depends on constants, and `None` if anything it
+    /// depends on is a variable, an external, or a vector.
+    ///
+    /// ```
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let two = g.push(Node::Constant(2.0));
+    /// let three = g.push(Node::Constant(3.0));
+    /// let product = g.push(two * three);
+    /// let x = g.push(Node::Variable);
+    /// let sum = g.push(product + x);
+    ///
+    /// assert_eq!(Some(6.0), g.constant_value(product));
+    /// assert_eq!(None, g.constant_value(sum));
+    /// ```
+    pub fn constant_value(&self, idx: Idx) -> Option<f64> {
+        let subgraph = self.subgraph_for(&[idx]);
+        let depends_on_input = subgraph.indices.iter().any(|&index| {
+            matches!(
+                self[index],
+                Node::Variable | Node::External { .. } | Node::VectorConstant(_)
+            )
+        });
+        if depends_on_input {
+            None
+        } else {
+            Some(self.evaluate_subgraph(subgraph, HashMap::new())[&idx])
+        }
+    }
+
+    /// Writes the graph as one line per node: the index, the kind, and then either the constant
+    /// value or the child indices, all separated by commas. This is meant to be easy to edit by
+    /// hand.
+    ///
+    /// ```
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Constant(1.5));
+    /// let b = g.push(Node::Variable);
+    /// g.push(a + b);
+    ///
+    /// let text = g.to_edge_list();
+    /// assert_eq!("0,constant,1.5\n1,variable\n2,sum,0,1\n", text);
+    /// assert_eq!(g, Graph::from_edge_list(&text).unwrap());
+    /// ```
+    pub fn to_edge_list(&self) -> String {
+        let mut result = String::new();
+
+        for (i, node) in self.nodes.iter().enumerate() {
+            result.push_str(&format!("{},{}", i, node.kind()));
+            for parameter in node.parameters() {
+                result.push_str(&format!(",{}", parameter));
+            }
+            for child in node.children() {
+                result.push_str(&format!(",{}", child.0));
+            }
+            result.push('\n');
+        }
+
+        result
+    }
+
+    /// Reads the format written by `to_edge_list`. Blank lines are ignored. Nodes must be listed
+    /// in order, and children must refer to nodes that have already been listed.
+    ///
+    /// ```
+    /// use exploring_computation_graphs_in_rust::enum_graph::Graph;
+    ///
+    /// let error = Graph::from_edge_list("0,variable\n1,sum,0,7\n").unwrap_err();
+    /// assert_eq!(2, error.line);
+    ///
+    /// assert!(Graph::from_edge_list("0,constant,two").is_err());
+    /// assert!(Graph::from_edge_list("0,frobnicate").is_err());
+    /// assert!(Graph::from_edge_list("0,variable\n1,exp,0,0").is_err());
+    /// ```
+    pub fn from_edge_list(s: &str) -> Result<Graph, ParseError> {
+        let mut graph = Graph::default();
+
+        for (line_index, line) in s.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+            let node = Node::from_edge_list_line(line, line_index, graph.len())?;
+            graph.push(node);
+        }
+
+        Ok(graph)
+    }
+
+    /// Evaluates a graph in the format written by `to_edge_list` as it's read, one line at a
+    /// time, so the graph itself is never built. Variables are looked up in `values` by index.
+    /// Returns the value of every node, in index order. It's an error for a variable or external
+    /// not to have a value, and for the graph to have a vector constant, since only scalars are
+    /// streamed.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Constant(1.5));
+    /// let b = g.push(Node::Variable);
+    /// let sum = g.push(a + b);
+    /// g.push(Node::Exp { child: sum });
+    /// let bytes = g.to_edge_list().into_bytes();
+    ///
+    /// let values: HashMap<_, _> = vec![(1, 2.0)].into_iter().collect();
+    /// let streamed = Graph::evaluate_bytes(&bytes, &values).unwrap();
+    ///
+    /// let loaded = Graph::from_edge_list(std::str::from_utf8(&bytes).unwrap()).unwrap();
+    /// let expected = loaded.evaluate(vec![(b, 2.0)].into_iter().collect::<HashMap<_, _>>());
+    /// assert_eq!(4, streamed.len());
+    /// assert_eq!(expected[&sum], streamed[2]);
+    /// assert_eq!(3.5f64.exp(), streamed[3]);
+    ///
+    /// let error = Graph::evaluate_bytes(b"0,constant,1\n1,exp,0,0\n", &values).unwrap_err();
+    /// assert_eq!(2, error.line);
+    ///
+    /// // Only node 1 has a value
+    /// let error = Graph::evaluate_bytes(b"0,variable\n1,variable\n", &values).unwrap_err();
+    /// assert_eq!(1, error.line);
+    /// ```
+    pub fn evaluate_bytes(
+        bytes: &[u8],
+        values: &HashMap<usize, f64>,
+    ) -> Result<Vec<f64>, ParseError> {
+        let mut result: Vec<f64> = Vec::new();
+
+        for (line_index, line) in bytes.split(|&byte| byte == b'\n').enumerate() {
+            let line = str::from_utf8(line).map_err(|_| ParseError {
+                line: line_index + 1,
+                message: "invalid UTF-8".to_string(),
+            })?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let index = result.len();
+            let node = Node::from_edge_list_line(line, line_index, index)?;
+            let unsupported = match node {
+                Node::VectorConstant(_) => Some("vector constants can't be streamed".to_string()),
+                Node::Normalize { .. } => Some("normalize nodes can't be streamed".to_string()),
+                Node::MatMul { .. } => Some("matmul nodes can't be streamed".to_string()),
+                Node::Variable | Node::External { .. } if !values.contains_key(&index) => {
+                    Some(format!("node {} has no value", index))
+                }
+                _ => None,
+            };
+            if let Some(message) = unsupported {
+                return Err(ParseError {
+                    line: line_index + 1,
+                    message,
+                });
+            }
+            let mut node_values: HashMap<Idx, f64> = node
+                .children()
+                .into_iter()
+                .map(|child| (child, result[child.0]))
+                .collect();
+            if let Some(&value) = values.get(&index) {
+                node_values.insert(Idx(index), value);
+            }
+            result.push(node.get_value(&Idx(index), &node_values));
+        }
+
+        Ok(result)
+    }
+
+    /// Writes only what `output` depends on, in the format of `to_edge_list`. The nodes are
+    /// renumbered from zero in the same order, so `output` is always the last line.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let unused = g.push(Node::Variable);
+    /// let x = g.push(Node::Variable);
+    /// let two = g.push(Node::Constant(2.0));
+    /// let product = g.push(x * two);
+    /// g.push(unused + product);
+    ///
+    /// let text = g.serialize_subgraph(product);
+    /// assert_eq!("0,variable\n1,constant,2\n2,product,0,1\n", text);
+    ///
+    /// let extracted = Graph::from_edge_list(&text).unwrap();
+    /// let output = extracted.roots()[0];
+    /// let extracted_x = extracted.relevant_variables(output).into_iter().next().unwrap();
+    /// let values: HashMap<_, _> = vec![(unused, 0.0), (x, 1.5)].into_iter().collect();
+    /// let extracted_values: HashMap<_, _> = vec![(extracted_x, 1.5)].into_iter().collect();
+    /// assert_eq!(g.evaluate(values)[&product], extracted.evaluate(extracted_values)[&output]);
+    /// ```
+    pub fn serialize_subgraph(&self, output: Idx) -> String {
+        let mut extracted = Graph::default();
+        let mut remap: HashMap<Idx, Idx> = HashMap::new();
+        for &index in self.subgraph_for(&[output]).indices() {
+            let node = self[index].map_children(|child| remap[&child]);
+            remap.insert(index, extracted.push(node));
+        }
+        extracted.to_edge_list()
+    }
+}
+
+impl Index<Idx> for Graph {
+    type Output = Node;
+
+    fn index(&self, index: Idx) -> &Node {
+        &self.nodes[index.0]
+    }
+}
@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+
+use super::{Graph, GraphError, Idx, Node};
+
+/// The values assigned to variables for one evaluation. Anything that evaluates a graph accepts
+/// `Into<Bindings>`, so a plain `HashMap<Idx, f64>` works too.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Bindings {
+    values: HashMap<Idx, f64>,
+}
+
+impl Bindings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts building a set of bindings that will be checked against a graph.
+    pub fn builder() -> BindingsBuilder {
+        BindingsBuilder::default()
+    }
+
+    /// Sets the value of a variable. This doesn't check that `idx` is a variable; use the
+    /// builder for that.
+    pub fn set(&mut self, idx: Idx, value: f64) {
+        self.values.insert(idx, value);
+    }
+
+    pub fn get(&self, idx: Idx) -> Option<f64> {
+        self.values.get(&idx).cloned()
+    }
+
+    /// Returns an error if any bound index isn't a `Variable` node in `graph`.
+    pub fn validate(&self, graph: &Graph) -> Result<(), GraphError> {
+        for &idx in self.values.keys() {
+            if idx.0 >= graph.len() {
+                return Err(GraphError::OutOfBounds(idx));
+            }
+            if graph[idx] != Node::Variable {
+                return Err(GraphError::NotAVariable(idx));
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn into_map(self) -> HashMap<Idx, f64> {
+        self.values
+    }
+}
+
+impl From<HashMap<Idx, f64>> for Bindings {
+    fn from(values: HashMap<Idx, f64>) -> Self {
+        Self { values }
+    }
+}
+
+/// Collects variable values and then checks them against a graph.
+///
+/// ```
+/// use exploring_computation_graphs_in_rust::enum_graph::{Bindings, Graph, GraphError, Node};
+///
+/// let mut g = Graph::default();
+/// let a = g.push(Node::Constant(1.0));
+/// let b = g.push(Node::Variable);
+/// let c = g.push(a + b);
+///
+/// let bindings = Bindings::builder().bind(b, 2.0).build(&g).unwrap();
+/// assert_eq!(Some(2.0), bindings.get(b));
+/// assert_eq!(3.0, g.evaluate(bindings)[&c]);
+///
+/// // Constants can't be bound
+/// let error = Bindings::builder().bind(a, 2.0).build(&g).unwrap_err();
+/// assert_eq!(GraphError::NotAVariable(a), error);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct BindingsBuilder {
+    bindings: Bindings,
+}
+
+impl BindingsBuilder {
+    pub fn bind(mut self, idx: Idx, value: f64) -> Self {
+        self.bindings.set(idx, value);
+        self
+    }
+
+    pub fn build(self, graph: &Graph) -> Result<Bindings, GraphError> {
+        self.bindings.validate(graph)?;
+        Ok(self.bindings)
+    }
+}
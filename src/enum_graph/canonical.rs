@@ -0,0 +1,331 @@
+use std::collections::{HashMap, HashSet};
+
+use super::{Closure, Graph, Idx, Node};
+
+/// A node described only in terms of its structure: variables are numbered in the order they're
+/// first reached, and children are referred to by their position in the canonical table.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+struct CanonicalNode {
+    kind: &'static str,
+    parameters: Vec<u64>,
+    children: Vec<usize>,
+    variable: Option<usize>,
+}
+
+impl CanonicalNode {
+    /// Describes `node`, given what its children and (if it's a variable) it are numbered as.
+    fn new(node: &Node, children: Vec<usize>, variable: Option<usize>) -> Self {
+        let mut parameters: Vec<u64> = node.parameters().iter().map(|p| p.to_bits()).collect();
+        // Custom nodes are only the same if they share their closures
+        if let Node::Custom { eval, grad, .. } = node {
+            parameters.push(eval.address() as u64);
+            parameters.push(grad.as_ref().map_or(0, Closure::address) as u64);
+        }
+        CanonicalNode {
+            kind: node.kind(),
+            parameters,
+            children,
+            variable,
+        }
+    }
+}
+
+/// Builds a table of distinct nodes by walking depth-first from the outputs of a graph. Two
+/// graphs that compute the same expressions produce the same table, no matter what order their
+/// nodes were pushed in or what their variables were called.
+#[derive(Debug, Default, PartialEq)]
+struct Canonicalizer {
+    table: Vec<CanonicalNode>,
+    roots: Vec<usize>,
+}
+
+impl Canonicalizer {
+    fn new(graph: &Graph) -> Self {
+        Self::with_positions(graph).0
+    }
+
+    /// Also returns the position of each of the graph's nodes in the table.
+    fn with_positions(graph: &Graph) -> (Self, HashMap<Idx, usize>) {
+        let mut canonicalizer = Self::default();
+        let mut interned = HashMap::new();
+        let mut visited = HashMap::new();
+        let mut variable_count = 0;
+
+        let used: HashSet<Idx> = graph
+            .nodes
+            .iter()
+            .flat_map(|node| node.children())
+            .collect();
+        for root in (0..graph.len()).map(Idx).filter(|idx| !used.contains(idx)) {
+            let id = canonicalizer.visit(
+                graph,
+                root,
+                &mut interned,
+                &mut visited,
+                &mut variable_count,
+            );
+            canonicalizer.roots.push(id);
+        }
+
+        (canonicalizer, visited)
+    }
+
+    fn visit(
+        &mut self,
+        graph: &Graph,
+        idx: Idx,
+        interned: &mut HashMap<CanonicalNode, usize>,
+        visited: &mut HashMap<Idx, usize>,
+        variable_count: &mut usize,
+    ) -> usize {
+        if let Some(&id) = visited.get(&idx) {
+            return id;
+        }
+
+        let node = &graph[idx];
+        let children = node
+            .children()
+            .into_iter()
+            .map(|child| self.visit(graph, child, interned, visited, variable_count))
+            .collect();
+        let variable = if let Node::Variable = node {
+            *variable_count += 1;
+            Some(*variable_count - 1)
+        } else {
+            None
+        };
+        let canonical = CanonicalNode::new(node, children, variable);
+
+        let table = &mut self.table;
+        let id = *interned.entry(canonical.clone()).or_insert_with(|| {
+            table.push(canonical);
+            table.len() - 1
+        });
+        visited.insert(idx, id);
+        id
+    }
+}
+
+impl Graph {
+    /// Returns true if the two graphs compute the same expressions once their variables are
+    /// consistently renamed. Duplicated nodes and the order in which nodes were pushed don't
+    /// matter, but the order of children does. Outputs are compared in index order.
+    ///
+    /// ```
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// // 2 * a + b
+    /// let mut g1 = Graph::default();
+    /// let two = g1.push(Node::Constant(2.0));
+    /// let a = g1.push(Node::Variable);
+    /// let b = g1.push(Node::Variable);
+    /// let product = g1.push(two * a);
+    /// g1.push(product + b);
+    ///
+    /// // The same thing, built in a different order with different variables
+    /// let mut g2 = Graph::default();
+    /// let x = g2.push(Node::Variable);
+    /// let y = g2.push(Node::Variable);
+    /// let two = g2.push(Node::Constant(2.0));
+    /// let product = g2.push(two * x);
+    /// g2.push(product + y);
+    /// assert!(g1.is_isomorphic(&g2));
+    ///
+    /// // 2 * a + a is different
+    /// let mut g3 = Graph::default();
+    /// let two = g3.push(Node::Constant(2.0));
+    /// let a = g3.push(Node::Variable);
+    /// let product = g3.push(two * a);
+    /// g3.push(product + a);
+    /// assert!(!g1.is_isomorphic(&g3));
+    /// ```
+    pub fn is_isomorphic(&self, other: &Graph) -> bool {
+        Canonicalizer::new(self) == Canonicalizer::new(other)
+    }
+
+    /// Rebuilds the graph in a canonical order: depth-first from the outputs in index order,
+    /// with children before their parents and duplicated nodes merged. Graphs that
+    /// `is_isomorphic` considers the same come out identical. Also returns where each of the
+    /// original nodes ended up.
+    ///
+    /// ```
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// // 2 * a + b
+    /// let mut g1 = Graph::default();
+    /// let two = g1.push(Node::Constant(2.0));
+    /// let a = g1.push(Node::Variable);
+    /// let b = g1.push(Node::Variable);
+    /// let product = g1.push(two * a);
+    /// g1.push(product + b);
+    ///
+    /// // The same thing, built in a different order
+    /// let mut g2 = Graph::default();
+    /// let y = g2.push(Node::Variable);
+    /// let x = g2.push(Node::Variable);
+    /// let two = g2.push(Node::Constant(2.0));
+    /// let product = g2.push(two * x);
+    /// g2.push(product + y);
+    /// assert_ne!(g1, g2);
+    ///
+    /// let (canonical1, remap1) = g1.canonicalize();
+    /// let (canonical2, remap2) = g2.canonicalize();
+    /// assert_eq!(canonical1, canonical2);
+    /// assert_eq!(remap1[&a], remap2[&x]);
+    /// assert_eq!(remap1[&b], remap2[&y]);
+    /// ```
+    pub fn canonicalize(&self) -> (Graph, HashMap<Idx, Idx>) {
+        let (canonicalizer, positions) = Canonicalizer::with_positions(self);
+
+        // Any of the original nodes at a position will do, since they're all the same
+        let mut originals = vec![None; canonicalizer.table.len()];
+        for (idx, &position) in &positions {
+            originals[position] = Some(*idx);
+        }
+
+        let mut graph = Graph::default();
+        for original in originals {
+            let node = self[original.expect("every position comes from a node")]
+                .map_children(|child| Idx(positions[&child]));
+            graph.push(node);
+        }
+
+        let remap = positions
+            .into_iter()
+            .map(|(idx, position)| (idx, Idx(position)))
+            .collect();
+        (graph, remap)
+    }
+
+    /// Finds nodes that compute exactly the same thing as an earlier node, which is where
+    /// deduplicating would help. Each pair is the first such node followed by a later copy of
+    /// it. Different variables are never the same, even though they look alike.
+    ///
+    /// ```
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Variable);
+    /// let first = g.push(a + b);
+    /// let second = g.push(a + b);
+    /// let swapped = g.push(b + a);
+    /// g.push(Node::Product { children: vec![first, second, swapped] });
+    ///
+    /// assert_eq!(vec![(first, second)], g.duplicate_subexpressions());
+    ///
+    /// // Custom nodes with different closures aren't the same, even with the same children
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// g.push(Node::custom(vec![a], |x| x[0].sin(), |x| vec![x[0].cos()]));
+    /// g.push(Node::custom(vec![a], |x| x[0].cos(), |x| vec![-x[0].sin()]));
+    /// assert!(g.duplicate_subexpressions().is_empty());
+    /// ```
+    pub fn duplicate_subexpressions(&self) -> Vec<(Idx, Idx)> {
+        self.representatives()
+            .into_iter()
+            .enumerate()
+            .filter(|&(i, first)| first.0 != i)
+            .map(|(i, first)| (first, Idx(i)))
+            .collect()
+    }
+
+    /// The number of nodes that would be left if every duplicate found by
+    /// `duplicate_subexpressions` were merged into the node it copies. The graph itself isn't
+    /// changed.
+    ///
+    /// ```
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Variable);
+    /// let first = g.push(a + b);
+    /// let second = g.push(a + b);
+    /// g.push(first * second);
+    ///
+    /// assert_eq!(g.len() - 1, g.distinct_subexpressions());
+    /// ```
+    pub fn distinct_subexpressions(&self) -> usize {
+        self.representatives()
+            .into_iter()
+            .enumerate()
+            .filter(|&(i, first)| first.0 == i)
+            .count()
+    }
+
+    /// Like `evaluate`, but a node that computes exactly the same thing as an earlier node (in
+    /// the sense of `duplicate_subexpressions`) reuses its value instead of being evaluated
+    /// again. The graph itself isn't changed.
+    ///
+    /// ```
+    /// use std::cell::Cell;
+    /// use std::collections::HashMap;
+    /// use std::rc::Rc;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let calls = Rc::new(Cell::new(0));
+    /// let counter = calls.clone();
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let exp = Node::custom(
+    ///     vec![a],
+    ///     move |inputs| {
+    ///         counter.set(counter.get() + 1);
+    ///         inputs[0].exp()
+    ///     },
+    ///     |inputs| vec![inputs[0].exp()],
+    /// );
+    /// // Both copies share the same closures, so they're the same subexpression
+    /// let first = g.push(exp.clone());
+    /// let second = g.push(exp);
+    /// let sum = g.push(first + second);
+    ///
+    /// let values: HashMap<_, _> = vec![(a, 1.0)].into_iter().collect();
+    /// let memoized = g.evaluate_memoized(values.clone());
+    /// assert_eq!(1, calls.get());
+    /// assert_eq!(2.0 * 1f64.exp(), memoized[&sum]);
+    ///
+    /// assert_eq!(memoized, g.evaluate(values));
+    /// assert_eq!(3, calls.get());
+    /// ```
+    pub fn evaluate_memoized(&self, values: HashMap<Idx, f64>) -> HashMap<Idx, f64> {
+        let mut result = values;
+
+        for (i, first) in self.representatives().into_iter().enumerate() {
+            let index = Idx(i);
+            let value = if first == index {
+                self[index].get_value(&index, &result)
+            } else {
+                result[&first]
+            };
+            result.insert(index, value);
+        }
+
+        result
+    }
+
+    /// For each node, the first node with exactly the same structure, which might be itself.
+    fn representatives(&self) -> Vec<Idx> {
+        let mut first_seen: HashMap<CanonicalNode, Idx> = HashMap::new();
+        let mut representatives: Vec<Idx> = Vec::with_capacity(self.len());
+
+        for (i, node) in self.nodes.iter().enumerate() {
+            let children = node
+                .children()
+                .iter()
+                .map(|child| representatives[child.0].0)
+                .collect();
+            let variable = if let Node::Variable = node {
+                Some(i)
+            } else {
+                None
+            };
+            let canonical = CanonicalNode::new(node, children, variable);
+            representatives.push(*first_seen.entry(canonical).or_insert(Idx(i)));
+        }
+
+        representatives
+    }
+}
@@ -0,0 +1,93 @@
+use super::{Graph, Idx, Node};
+
+/// The graph under construction inside `Graph::build`, with a method for each common kind of
+/// node. Anything else can be pushed with `push`.
+#[derive(Debug, Default)]
+pub struct Context {
+    graph: Graph,
+}
+
+impl Context {
+    pub fn push(&mut self, node: Node) -> Idx {
+        self.graph.push(node)
+    }
+
+    pub fn constant(&mut self, value: f64) -> Idx {
+        self.push(Node::Constant(value))
+    }
+
+    pub fn var(&mut self) -> Idx {
+        self.push(Node::Variable)
+    }
+
+    pub fn add(&mut self, a: Idx, b: Idx) -> Idx {
+        self.push(a + b)
+    }
+
+    pub fn mul(&mut self, a: Idx, b: Idx) -> Idx {
+        self.push(a * b)
+    }
+
+    pub fn scale(&mut self, child: Idx, factor: f64) -> Idx {
+        self.push(child * factor)
+    }
+
+    pub fn sum(&mut self, children: &[Idx]) -> Idx {
+        self.push(Node::Sum {
+            children: children.to_vec(),
+        })
+    }
+
+    pub fn product(&mut self, children: &[Idx]) -> Idx {
+        self.push(Node::Product {
+            children: children.to_vec(),
+        })
+    }
+
+    pub fn exp(&mut self, child: Idx) -> Idx {
+        self.push(Node::Exp { child })
+    }
+
+    pub fn ln(&mut self, child: Idx) -> Idx {
+        self.push(Node::Ln { child })
+    }
+
+    pub fn tanh(&mut self, child: Idx) -> Idx {
+        self.push(Node::Tanh { child })
+    }
+
+    pub fn pow(&mut self, child: Idx, exponent: f64) -> Idx {
+        self.push(Node::Pow { child, exponent })
+    }
+}
+
+impl Graph {
+    /// Builds a graph inside the closure `f`, which gets a `Context` to push nodes with and
+    /// returns the root. Returns the finished graph and that root.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::Graph;
+    ///
+    /// // 2 * a + a * b
+    /// let mut inputs = vec![];
+    /// let (g, root) = Graph::build(|cx| {
+    ///     let two = cx.constant(2.0);
+    ///     let a = cx.var();
+    ///     let b = cx.var();
+    ///     inputs = vec![a, b];
+    ///     let two_a = cx.mul(two, a);
+    ///     let a_b = cx.mul(a, b);
+    ///     cx.add(two_a, a_b)
+    /// });
+    ///
+    /// assert_eq!(6, g.len());
+    /// let values: HashMap<_, _> = vec![(inputs[0], 3.0), (inputs[1], 4.0)].into_iter().collect();
+    /// assert_eq!(18.0, g.evaluate(values)[&root]);
+    /// ```
+    pub fn build<F: FnOnce(&mut Context) -> Idx>(f: F) -> (Graph, Idx) {
+        let mut context = Context::default();
+        let root = f(&mut context);
+        (context.graph, root)
+    }
+}
@@ -0,0 +1,123 @@
+use std::fmt;
+use std::ops::Deref;
+use std::rc::Rc;
+
+use super::{Idx, Node};
+
+/// A shared closure inside a `Node::Custom`. Closures can't be compared or printed, so two of
+/// these are equal only if they're the same closure, and they print without their contents.
+pub struct Closure<F: ?Sized>(Rc<F>);
+
+/// Computes a custom node's value from the values of its children.
+pub type CustomEval = Closure<dyn Fn(&[f64]) -> f64>;
+
+/// Computes the partial derivative of a custom node with respect to each of its children.
+pub type CustomGrad = Closure<dyn Fn(&[f64]) -> Vec<f64>>;
+
+impl<F: ?Sized> Clone for Closure<F> {
+    fn clone(&self) -> Self {
+        Closure(Rc::clone(&self.0))
+    }
+}
+
+impl<F: ?Sized> fmt::Debug for Closure<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Closure({:p})", Rc::as_ptr(&self.0) as *const ())
+    }
+}
+
+impl<F: ?Sized> PartialEq for Closure<F> {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::as_ptr(&self.0) as *const () == Rc::as_ptr(&other.0) as *const ()
+    }
+}
+
+impl<F: ?Sized> Deref for Closure<F> {
+    type Target = F;
+
+    fn deref(&self) -> &F {
+        &self.0
+    }
+}
+
+impl<F: ?Sized> Closure<F> {
+    /// The address of the closure, which tells different custom nodes apart.
+    pub(crate) fn address(&self) -> usize {
+        Rc::as_ptr(&self.0) as *const () as usize
+    }
+}
+
+impl Node {
+    /// Builds a `Custom` node. `eval` gets the values of the children, in order, and `grad`
+    /// gets the same values and returns the partial derivative with respect to each child.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Variable);
+    /// let hypot = g.push(Node::custom(
+    ///     vec![a, b],
+    ///     |x| x[0].hypot(x[1]),
+    ///     |x| {
+    ///         let length = x[0].hypot(x[1]);
+    ///         vec![x[0] / length, x[1] / length]
+    ///     },
+    /// ));
+    ///
+    /// let values: HashMap<_, _> = vec![(a, 3.0), (b, 4.0)].into_iter().collect();
+    /// assert_eq!(5.0, g.evaluate(values.clone())[&hypot]);
+    /// assert_eq!(vec![(a, 0.6), (b, 0.8)], g.gradient_vector(hypot, &values).unwrap());
+    /// ```
+    ///
+    /// `grad` only gives first derivatives. `Graph::derivative` turns each partial into another
+    /// custom node without a gradient, and anything that differentiates one of those nodes
+    /// again returns `GraphError::NotTwiceDifferentiable`.
+    ///
+    /// ```
+    /// use std::collections::{HashMap, HashSet};
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, GraphError, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let x = g.push(Node::Variable);
+    /// let sin = g.push(Node::custom(vec![x], |x| x[0].sin(), |x| vec![x[0].cos()]));
+    ///
+    /// let wrt: HashSet<_> = vec![x].into_iter().collect();
+    /// let (derivative, subgraph) = g.derivative(sin, wrt.clone()).unwrap();
+    /// let values: HashMap<_, _> = vec![(x, 1.0)].into_iter().collect();
+    /// assert_eq!(1f64.cos(), g.evaluate_subgraph(subgraph, values.clone())[&derivative]);
+    ///
+    /// // The second derivative of the custom node isn't known
+    /// let cos = match g.gradient_vector(derivative, &values) {
+    ///     Err(GraphError::NotTwiceDifferentiable(cos)) => cos,
+    ///     result => panic!("expected an error, got {:?}", result),
+    /// };
+    /// let error = g.derivative(derivative, wrt).err();
+    /// assert_eq!(Some(GraphError::NotTwiceDifferentiable(cos)), error);
+    /// ```
+    pub fn custom(
+        children: Vec<Idx>,
+        eval: impl Fn(&[f64]) -> f64 + 'static,
+        grad: impl Fn(&[f64]) -> Vec<f64> + 'static,
+    ) -> Node {
+        Node::Custom {
+            children,
+            eval: Closure(Rc::new(eval)),
+            grad: Some(Closure(Rc::new(grad))),
+        }
+    }
+
+    /// A custom node for one partial derivative of another, which can't be differentiated.
+    pub(crate) fn custom_partial(
+        children: Vec<Idx>,
+        eval: impl Fn(&[f64]) -> f64 + 'static,
+    ) -> Node {
+        Node::Custom {
+            children,
+            eval: Closure(Rc::new(eval)),
+            grad: None,
+        }
+    }
+}
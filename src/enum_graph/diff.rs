@@ -0,0 +1,83 @@
+use super::{Graph, Idx, Node};
+
+/// One difference between two graphs, as reported by `Graph::diff`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GraphDelta {
+    /// The other graph has a node here but this one doesn't.
+    Added(Idx),
+    /// This graph has a node here but the other one doesn't.
+    Removed(Idx),
+    /// Both graphs have a constant here, with different values.
+    ChangedConstant { idx: Idx, old: f64, new: f64 },
+    /// Both graphs have the same kind of node here, but with different children.
+    ChangedChildren {
+        idx: Idx,
+        old: Vec<Idx>,
+        new: Vec<Idx>,
+    },
+    /// The node was replaced by a different kind of node, or by one with different parameters.
+    ChangedNode(Idx),
+}
+
+impl Graph {
+    /// Reports how `other` differs from this graph, comparing the nodes at each index. This
+    /// assumes that the graphs share a prefix, like after a pass that edits or appends nodes, and
+    /// it doesn't try to match up nodes that moved.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, GraphDelta, Node};
+    ///
+    /// // a * (2 + 3)
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let two = g.push(Node::Constant(2.0));
+    /// let three = g.push(Node::Constant(3.0));
+    /// let sum = g.push(two + three);
+    /// let product = g.push(a * sum);
+    ///
+    /// // Folding the constants leaves a * 5
+    /// let (folded, _) = g.partial_evaluate(HashMap::new());
+    /// assert_eq!(
+    ///     vec![
+    ///         GraphDelta::ChangedConstant { idx: two, old: 2.0, new: 5.0 },
+    ///         GraphDelta::ChangedNode(three),
+    ///         GraphDelta::Removed(sum),
+    ///         GraphDelta::Removed(product),
+    ///     ],
+    ///     g.diff(&folded)
+    /// );
+    /// assert!(g.diff(&g).is_empty());
+    /// ```
+    pub fn diff(&self, other: &Graph) -> Vec<GraphDelta> {
+        let shared = self.len().min(other.len());
+        let mut deltas = Vec::new();
+
+        for i in 0..shared {
+            let idx = Idx(i);
+            let (old, new) = (&self[idx], &other[idx]);
+            if old == new {
+                continue;
+            }
+            deltas.push(match (old, new) {
+                (Node::Constant(old), Node::Constant(new)) => GraphDelta::ChangedConstant {
+                    idx,
+                    old: *old,
+                    new: *new,
+                },
+                _ if old.kind() == new.kind() && old.parameters() == new.parameters() => {
+                    GraphDelta::ChangedChildren {
+                        idx,
+                        old: old.children(),
+                        new: new.children(),
+                    }
+                }
+                _ => GraphDelta::ChangedNode(idx),
+            });
+        }
+
+        deltas.extend((shared..other.len()).map(|i| GraphDelta::Added(Idx(i))));
+        deltas.extend((shared..self.len()).map(|i| GraphDelta::Removed(Idx(i))));
+        deltas
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::ops::{Add, Mul};
+
+use super::{Graph, GraphError, Idx, Node, Subgraph};
+
+/// A dual number: a value along with its derivative in some direction.
+///
+/// ```
+/// use exploring_computation_graphs_in_rust::enum_graph::Dual;
+///
+/// let x = Dual::new(3.0, 1.0);
+/// assert_eq!(Dual::new(9.0, 6.0), x * x);
+/// assert_eq!(Dual::new(5.0, 1.0), x + Dual::constant(2.0));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Dual {
+    pub value: f64,
+    pub deriv: f64,
+}
+
+impl Dual {
+    pub fn new(value: f64, deriv: f64) -> Self {
+        Self { value, deriv }
+    }
+
+    /// A value that doesn't change in the direction we're differentiating.
+    pub fn constant(value: f64) -> Self {
+        Self::new(value, 0.0)
+    }
+}
+
+impl Add for Dual {
+    type Output = Dual;
+
+    fn add(self, rhs: Dual) -> Dual {
+        Dual::new(self.value + rhs.value, self.deriv + rhs.deriv)
+    }
+}
+
+impl Mul for Dual {
+    type Output = Dual;
+
+    fn mul(self, rhs: Dual) -> Dual {
+        Dual::new(
+            self.value * rhs.value,
+            self.deriv * rhs.value + self.value * rhs.deriv,
+        )
+    }
+}
+
+impl Graph {
+    /// Computes the value and the directional derivative of every node in one forward pass,
+    /// without adding any nodes to the graph. Each variable's `deriv` gives the direction.
+    ///
+    /// Each node's derivative follows from the chain rule: the sum over its children of the
+    /// partial derivative with respect to that child times the child's derivative.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Dual, Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Variable);
+    /// let product = g.push(a * b);
+    ///
+    /// // Differentiate with respect to a
+    /// let values: HashMap<_, _> = vec![(a, Dual::new(3.0, 1.0)), (b, Dual::constant(4.0))]
+    ///     .into_iter()
+    ///     .collect();
+    /// assert_eq!(Dual::new(12.0, 4.0), g.evaluate_dual(values).unwrap()[&product]);
+    /// ```
+    pub fn evaluate_dual(
+        &self,
+        values: HashMap<Idx, Dual>,
+    ) -> Result<HashMap<Idx, Dual>, GraphError> {
+        let subgraph = self.as_subgraph();
+        self.check_differentiable(&subgraph)?;
+        Ok(self.evaluate_dual_subgraph(&subgraph, values))
+    }
+
+    /// Like `evaluate_dual`, but only for the nodes in `subgraph`, which must already have been
+    /// checked with `check_differentiable`.
+    fn evaluate_dual_subgraph(
+        &self,
+        subgraph: &Subgraph,
+        values: HashMap<Idx, Dual>,
+    ) -> HashMap<Idx, Dual> {
+        let mut plain_values: HashMap<Idx, f64> = HashMap::new();
+        let mut result = values;
+
+        for &index in subgraph.indices() {
+            let node = &self[index];
+            let dual = match node {
+                Node::Variable | Node::External { .. } => result[&index],
+                _ => {
+                    let value = node.get_value(&index, &plain_values);
+                    plain_values.insert(index, value);
+                    let deriv = node
+                        .children()
+                        .into_iter()
+                        .zip(node.local_gradients(&index, &plain_values))
+                        .map(|(child, partial)| partial * result[&child].deriv)
+                        .sum();
+                    Dual::new(value, deriv)
+                }
+            };
+            plain_values.insert(index, dual.value);
+            result.insert(index, dual);
+        }
+
+        result
+    }
+
+    /// Multiplies the Hessian of `of` by the vector `v`, at the point `at`, without building
+    /// the whole Hessian. This uses forward mode over the symbolic gradient: it pushes the
+    /// derivative of `of` with respect to each variable in `wrt`, then evaluates those
+    /// derivatives with dual numbers pointing in the direction `v`. Variables missing from `v`
+    /// have a direction of zero.
+    ///
+    /// The result maps each variable in `wrt` to its entry of the product. Only the nodes that
+    /// the gradients depend on are evaluated, so `at` only needs values for those variables.
+    ///
+    /// A `Custom` node only knows its first derivatives, so if `of` depends on one, this
+    /// returns `GraphError::NotTwiceDifferentiable` without changing the graph.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, GraphError, Node};
+    ///
+    /// // a^2 + 3ab + 2b^2 has the Hessian [[2, 3], [3, 4]] everywhere
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Variable);
+    /// let three = g.push(Node::Constant(3.0));
+    /// let a_squared = g.push(Node::Pow { child: a, exponent: 2.0 });
+    /// let cross = g.push(Node::Product { children: vec![three, a, b] });
+    /// let b_squared = g.push(Node::Pow { child: b, exponent: 2.0 });
+    /// let double_b_squared = g.push(b_squared * 2.0);
+    /// let f = g.push(Node::Sum { children: vec![a_squared, cross, double_b_squared] });
+    ///
+    /// let v: HashMap<_, _> = vec![(a, 1.0), (b, 2.0)].into_iter().collect();
+    /// let at: HashMap<_, _> = vec![(a, 0.5), (b, -7.0)].into_iter().collect();
+    /// let product = g.hessian_vector_product(f, &[a, b], &v, &at).unwrap();
+    /// let expected: HashMap<_, _> = vec![(a, 8.0), (b, 11.0)].into_iter().collect();
+    /// assert_eq!(expected, product);
+    ///
+    /// // A variable that f doesn't use doesn't need a value
+    /// let unused = g.push(Node::Variable);
+    /// g.push(Node::Exp { child: unused });
+    /// assert_eq!(expected, g.hessian_vector_product(f, &[a, b], &v, &at).unwrap());
+    ///
+    /// let custom = g.push(Node::custom(vec![a], |x| x[0].sin(), |x| vec![x[0].cos()]));
+    /// assert_eq!(
+    ///     Err(GraphError::NotTwiceDifferentiable(custom)),
+    ///     g.hessian_vector_product(custom, &[a], &v, &at)
+    /// );
+    /// ```
+    pub fn hessian_vector_product(
+        &mut self,
+        of: Idx,
+        wrt: &[Idx],
+        v: &HashMap<Idx, f64>,
+        at: &HashMap<Idx, f64>,
+    ) -> Result<HashMap<Idx, f64>, GraphError> {
+        if let Some(&custom) = self
+            .subgraph_for(&[of])
+            .indices()
+            .iter()
+            .find(|&&idx| matches!(self[idx], Node::Custom { .. }))
+        {
+            return Err(GraphError::NotTwiceDifferentiable(custom));
+        }
+
+        let gradients: Vec<(Idx, Idx)> = wrt
+            .iter()
+            .map(|&variable| {
+                let (gradient, _) =
+                    self.derivative_pruned(of, vec![variable].into_iter().collect())?;
+                Ok((variable, gradient))
+            })
+            .collect::<Result<_, GraphError>>()?;
+
+        let duals = at
+            .iter()
+            .map(|(&idx, &value)| (idx, Dual::new(value, v.get(&idx).cloned().unwrap_or(0.0))))
+            .collect();
+        let gradient_indices: Vec<Idx> = gradients.iter().map(|&(_, gradient)| gradient).collect();
+        let result = self.evaluate_dual_subgraph(&self.subgraph_for(&gradient_indices), duals);
+
+        Ok(gradients
+            .into_iter()
+            .map(|(variable, gradient)| (variable, result[&gradient].deriv))
+            .collect())
+    }
+}
@@ -0,0 +1,103 @@
+use std::collections::{HashMap, HashSet};
+
+use super::{Bindings, Graph, Idx};
+
+/// Owns a graph and remembers the value of every node it has computed, so that asking for the
+/// same node twice only evaluates it once. After editing the graph through `graph_mut`, call
+/// `invalidate` on each edited node so that the next `get` recomputes what changed.
+pub struct Evaluator {
+    graph: Graph,
+    variable_to_value: HashMap<Idx, f64>,
+    cache: HashMap<Idx, f64>,
+}
+
+impl Evaluator {
+    pub fn new(graph: Graph, variable_to_value: impl Into<Bindings>) -> Self {
+        Self {
+            graph,
+            variable_to_value: variable_to_value.into().into_map(),
+            cache: HashMap::new(),
+        }
+    }
+
+    pub fn graph(&self) -> &Graph {
+        &self.graph
+    }
+
+    /// Edits to the graph aren't tracked, so any node whose children change must be passed to
+    /// `invalidate` afterwards.
+    pub fn graph_mut(&mut self) -> &mut Graph {
+        &mut self.graph
+    }
+
+    pub fn is_cached(&self, idx: Idx) -> bool {
+        self.cache.contains_key(&idx)
+    }
+
+    /// Returns the value of `idx`, computing only the nodes it depends on that aren't cached.
+    pub fn get(&mut self, idx: Idx) -> f64 {
+        for &index in self.graph.subgraph_for(&[idx]).indices() {
+            if self.cache.contains_key(&index) {
+                continue;
+            }
+            let value = match self.variable_to_value.get(&index) {
+                Some(&value) => value,
+                None => self.graph[index].get_value(&index, &self.cache),
+            };
+            self.cache.insert(index, value);
+        }
+        self.cache[&idx]
+    }
+
+    /// Forgets the cached values of `idx` and of every node that depends on it.
+    ///
+    /// Since parents always come after their children, one pass forwards from `idx` finds all
+    /// of its ancestors. Editing a node's children never changes who its ancestors are.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Evaluator, Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Variable);
+    /// let sum = g.push(a + b);
+    /// let product = g.push(sum * a);
+    /// let exp = g.push(Node::Exp { child: b });
+    ///
+    /// let values: HashMap<_, _> = vec![(a, 2.0), (b, 0.0)].into_iter().collect();
+    /// let mut evaluator = Evaluator::new(g, values);
+    /// assert_eq!(4.0, evaluator.get(product));
+    /// assert_eq!(1.0, evaluator.get(exp));
+    ///
+    /// // sum = a + a
+    /// evaluator.graph_mut().rewire(sum, vec![a, a]).unwrap();
+    /// evaluator.invalidate(sum);
+    /// assert!(!evaluator.is_cached(sum));
+    /// assert!(!evaluator.is_cached(product));
+    /// assert!(evaluator.is_cached(a));
+    /// assert!(evaluator.is_cached(b));
+    /// assert!(evaluator.is_cached(exp));
+    ///
+    /// assert_eq!(8.0, evaluator.get(product));
+    /// ```
+    pub fn invalidate(&mut self, idx: Idx) {
+        let mut dirty: HashSet<Idx> = HashSet::new();
+        dirty.insert(idx);
+
+        for i in idx.0 + 1..self.graph.len() {
+            let index = Idx(i);
+            if self.graph[index]
+                .children()
+                .iter()
+                .any(|child| dirty.contains(child))
+            {
+                dirty.insert(index);
+            }
+        }
+
+        for index in dirty {
+            self.cache.remove(&index);
+        }
+    }
+}
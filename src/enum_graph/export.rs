@@ -0,0 +1,157 @@
+use std::fmt;
+
+use super::{Graph, Idx, Node, ReduceOp};
+
+/// A simplified ONNX operator: its type, the nodes it reads, the node it writes, and any
+/// numeric attributes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OnnxOp {
+    pub op_type: &'static str,
+    pub inputs: Vec<Idx>,
+    pub output: Idx,
+    pub attributes: Vec<(&'static str, f64)>,
+}
+
+impl Graph {
+    /// Lists one operator per node, in evaluation order. This doesn't produce a real ONNX
+    /// model, but the operator types follow ONNX where there's an equivalent:
+    ///
+    /// - Variables become `Input` records, since ONNX models graph inputs separately
+    /// - `Product` becomes `Mul`, even with more than two inputs
+    /// - `Pow` and `Scale` store their constants as attributes, rather than as second inputs
+    /// - `Select` becomes `Where`, treating a condition greater than zero as true
+    /// - `Ewma`, `SafeDiv`, `Bucketize`, `Lerp`, and `Custom` have no ONNX equivalent, so they
+    ///   keep their own names
+    ///
+    /// ```
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node, OnnxOp};
+    ///
+    /// // 2 * a + a * b
+    /// let mut g = Graph::default();
+    /// let two = g.push(Node::Constant(2.0));
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Variable);
+    /// let left = g.push(two * a);
+    /// let right = g.push(a * b);
+    /// let sum = g.push(left + right);
+    ///
+    /// let op = |op_type, inputs, output, attributes| OnnxOp { op_type, inputs, output, attributes };
+    /// assert_eq!(
+    ///     vec![
+    ///         op("Constant", vec![], two, vec![("value", 2.0)]),
+    ///         op("Input", vec![], a, vec![]),
+    ///         op("Input", vec![], b, vec![]),
+    ///         op("Mul", vec![two, a], left, vec![]),
+    ///         op("Mul", vec![a, b], right, vec![]),
+    ///         op("Sum", vec![left, right], sum, vec![]),
+    ///     ],
+    ///     g.to_onnx_ops()
+    /// );
+    /// ```
+    pub fn to_onnx_ops(&self) -> Vec<OnnxOp> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| {
+                let (op_type, attributes) = match node {
+                    Node::Constant(value) => ("Constant", vec![("value", *value)]),
+                    Node::VectorConstant(values) => (
+                        "Constant",
+                        values.iter().map(|value| ("value", *value)).collect(),
+                    ),
+                    Node::Variable => ("Input", vec![]),
+                    Node::External { id } => ("Input", vec![("external_id", *id as f64)]),
+                    Node::Sum { .. } => ("Sum", vec![]),
+                    Node::Product { .. } => ("Mul", vec![]),
+                    Node::Exp { .. } => ("Exp", vec![]),
+                    Node::Ln { .. } => ("Log", vec![]),
+                    Node::LogSumExp { .. } => ("ReduceLogSumExp", vec![]),
+                    Node::SquaredNorm { .. } => ("ReduceSumSquare", vec![]),
+                    Node::Tanh { .. } => ("Tanh", vec![]),
+                    Node::Softplus { .. } => ("Softplus", vec![]),
+                    Node::Sinh { .. } => ("Sinh", vec![]),
+                    Node::Cosh { .. } => ("Cosh", vec![]),
+                    Node::Atan { .. } => ("Atan", vec![]),
+                    Node::Asin { .. } => ("Asin", vec![]),
+                    Node::Pow { exponent, .. } => ("Pow", vec![("exponent", *exponent)]),
+                    Node::PowNode { .. } => ("Pow", vec![]),
+                    Node::Gelu { .. } => ("Gelu", vec![]),
+                    Node::Identity { .. } | Node::Memo { .. } => ("Identity", vec![]),
+                    Node::Reshape { shape, .. } => (
+                        "Reshape",
+                        shape.iter().map(|&len| ("shape", len as f64)).collect(),
+                    ),
+                    Node::Max { .. } => ("Max", vec![]),
+                    Node::Reduce { op, .. } => match op {
+                        ReduceOp::Sum => ("Sum", vec![]),
+                        ReduceOp::Product => ("Mul", vec![]),
+                        ReduceOp::Max => ("Max", vec![]),
+                        ReduceOp::Min => ("Min", vec![]),
+                    },
+                    Node::Select { .. } => ("Where", vec![]),
+                    Node::Modulo { .. } => ("Mod", vec![]),
+                    Node::SafeDiv { default, .. } => ("SafeDiv", vec![("default", *default)]),
+                    Node::Greater { .. } => ("Greater", vec![]),
+                    Node::Equal { .. } => ("Equal", vec![]),
+                    Node::Sign { .. } => ("Sign", vec![]),
+                    Node::Normalize { .. } => ("LpNormalization", vec![("p", 2.0)]),
+                    Node::MatMul { .. } => ("MatMul", vec![]),
+                    Node::Bucketize { edges, .. } => (
+                        "Bucketize",
+                        edges.iter().map(|edge| ("edge", *edge)).collect(),
+                    ),
+                    Node::Lerp { xs, ys, .. } => (
+                        "Lerp",
+                        xs.iter()
+                            .map(|x| ("x", *x))
+                            .chain(ys.iter().map(|y| ("y", *y)))
+                            .collect(),
+                    ),
+                    Node::Ewma { alpha, .. } => ("Ewma", vec![("alpha", *alpha)]),
+                    Node::Scale { factor, .. } => ("Mul", vec![("factor", *factor)]),
+                    Node::Custom { .. } => ("Custom", vec![]),
+                };
+                OnnxOp {
+                    op_type,
+                    inputs: node.children(),
+                    output: Idx(i),
+                    attributes,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Prints one line per node: the index, the kind, any parameters, and then the children in
+/// brackets. This is meant for quick debugging, e.g. `2: sum [0, 1]`.
+///
+/// ```
+/// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+///
+/// let mut g = Graph::default();
+/// let a = g.push(Node::Constant(1.0));
+/// let b = g.push(Node::Variable);
+/// g.push(a + b);
+///
+/// let text = g.to_string();
+/// assert_eq!(3, text.lines().count());
+/// assert_eq!("0: constant 1\n1: variable\n2: sum [0, 1]\n", text);
+/// ```
+impl fmt::Display for Graph {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, node) in self.nodes.iter().enumerate() {
+            write!(f, "{}: {}", i, node.kind())?;
+            for parameter in node.parameters() {
+                write!(f, " {}", parameter)?;
+            }
+            let children = node.children();
+            if !children.is_empty() {
+                let children: Vec<String> =
+                    children.iter().map(|child| child.0.to_string()).collect();
+                write!(f, " [{}]", children.join(", "))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
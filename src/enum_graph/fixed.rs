@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+use std::ops::{Add, Mul};
+
+use super::{Graph, GraphError, Idx, Scalar};
+
+/// The number of fractional bits in a `Fixed`
+const FRACTION_BITS: u32 = 16;
+
+/// A Q16.16 fixed-point number: an `i32` counting in steps of `1 / 65536`, for running graphs
+/// on hardware without floating point.
+///
+/// The arithmetic is checked: if a result doesn't fit in an `i32`, `checked_add` and
+/// `checked_mul` return `None`, and `+` and `*` panic, rather than silently wrapping. Products
+/// are rounded towards negative infinity.
+///
+/// ```
+/// use exploring_computation_graphs_in_rust::enum_graph::Fixed;
+///
+/// assert_eq!(Fixed::from_f64(0.75), Fixed::from_f64(0.5) + Fixed::from_f64(0.25));
+/// assert_eq!(Fixed::from_f64(-1.5), Fixed::from_f64(0.5) * Fixed::from_f64(-3.0));
+/// assert_eq!(1 << 16, Fixed::from_f64(1.0).to_bits());
+/// ```
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Fixed(i32);
+
+impl Fixed {
+    pub fn from_bits(bits: i32) -> Self {
+        Fixed(bits)
+    }
+
+    pub fn to_bits(self) -> i32 {
+        self.0
+    }
+
+    /// The closest `Fixed`, or `None` if `value` is out of range or NaN.
+    pub fn try_from_f64(value: f64) -> Option<Self> {
+        let bits = (value * f64::from(1 << FRACTION_BITS)).round();
+        if bits >= f64::from(i32::MIN) && bits <= f64::from(i32::MAX) {
+            Some(Fixed(bits as i32))
+        } else {
+            None
+        }
+    }
+
+    /// # Panics
+    ///
+    /// Panics if `value` is out of range or NaN.
+    pub fn from_f64(value: f64) -> Self {
+        Self::try_from_f64(value)
+            .unwrap_or_else(|| panic!("{} doesn't fit in a Q16.16 fixed-point number", value))
+    }
+
+    /// The exact `f64`, for comparing with the floating-point evaluators.
+    pub fn to_f64(self) -> f64 {
+        f64::from(self.0) / f64::from(1 << FRACTION_BITS)
+    }
+
+    /// The sum, or `None` if it doesn't fit.
+    pub fn checked_add(self, rhs: Fixed) -> Option<Fixed> {
+        self.0.checked_add(rhs.0).map(Fixed)
+    }
+
+    /// The product, or `None` if it doesn't fit.
+    pub fn checked_mul(self, rhs: Fixed) -> Option<Fixed> {
+        // Both operands carry the scale, so the wide product has to be shifted back down once
+        let product = (i64::from(self.0) * i64::from(rhs.0)) >> FRACTION_BITS;
+        if product < i64::from(i32::MIN) || product > i64::from(i32::MAX) {
+            None
+        } else {
+            Some(Fixed(product as i32))
+        }
+    }
+}
+
+impl Add for Fixed {
+    type Output = Fixed;
+
+    fn add(self, rhs: Fixed) -> Fixed {
+        self.checked_add(rhs)
+            .unwrap_or_else(|| panic!("overflow adding {:?} and {:?}", self, rhs))
+    }
+}
+
+impl Mul for Fixed {
+    type Output = Fixed;
+
+    fn mul(self, rhs: Fixed) -> Fixed {
+        self.checked_mul(rhs)
+            .unwrap_or_else(|| panic!("overflow multiplying {:?} and {:?}", self, rhs))
+    }
+}
+
+impl Scalar for Fixed {
+    fn zero() -> Self {
+        Fixed(0)
+    }
+
+    fn one() -> Self {
+        Fixed(1 << FRACTION_BITS)
+    }
+
+    fn checked_add(self, rhs: Self) -> Option<Self> {
+        Fixed::checked_add(self, rhs)
+    }
+
+    fn checked_mul(self, rhs: Self) -> Option<Self> {
+        Fixed::checked_mul(self, rhs)
+    }
+
+    fn from_f64(value: f64) -> Option<Self> {
+        Fixed::try_from_f64(value)
+    }
+
+    fn unsupported(idx: Idx) -> GraphError {
+        GraphError::NotFixedPoint(idx)
+    }
+}
+
+impl Graph {
+    /// Evaluates the graph in fixed point, with a `Fixed` for each variable. This is
+    /// `evaluate_scalar`: only constants in range, variables, and sums, products, and scales of
+    /// them can be evaluated this way, and anything else returns `GraphError::NotFixedPoint`. If
+    /// a value doesn't fit in a `Fixed`, this returns `GraphError::Overflow`.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Fixed, Graph, GraphError, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Variable);
+    /// let sum = g.push(a + b);
+    /// let product = g.push(sum * 2.0);
+    ///
+    /// let values: HashMap<_, _> =
+    ///     vec![(a, Fixed::from_f64(0.5)), (b, Fixed::from_f64(0.25))].into_iter().collect();
+    /// let result = g.evaluate_fixed(values).unwrap();
+    /// assert_eq!(Fixed::from_f64(0.75), result[&sum]);
+    /// assert_eq!(1.5, result[&product].to_f64());
+    ///
+    /// // Q16.16 only goes up to 32768
+    /// let big = Fixed::from_f64(20000.0);
+    /// let values: HashMap<_, _> = vec![(a, big), (b, big)].into_iter().collect();
+    /// assert_eq!(Err(GraphError::Overflow(sum)), g.evaluate_fixed(values));
+    /// ```
+    pub fn evaluate_fixed(
+        &self,
+        values: HashMap<Idx, Fixed>,
+    ) -> Result<HashMap<Idx, Fixed>, GraphError> {
+        self.evaluate_scalar(values)
+    }
+}
@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+
+use super::{Graph, Idx, Node, ReduceOp};
+
+impl Node {
+    /// Writes this node as an expression, given the expressions for its children. Variables are
+    /// named `x` followed by their index.
+    fn to_infix(&self, my_index: Idx, children: &[String]) -> String {
+        match self {
+            Node::Constant(value) => value.to_string(),
+            Node::Variable => format!("x{}", my_index.0),
+            Node::Sum { .. }
+            | Node::Reduce {
+                op: ReduceOp::Sum, ..
+            } => {
+                format!("({})", children.join(" + "))
+            }
+            Node::Product { .. }
+            | Node::Reduce {
+                op: ReduceOp::Product,
+                ..
+            } => {
+                format!("({})", children.join(" * "))
+            }
+            Node::Scale { factor, .. } => format!("({} * {})", children[0], factor),
+            Node::Pow { exponent, .. } => format!("({} ^ {})", children[0], exponent),
+            Node::PowNode { .. } => format!("({} ^ {})", children[0], children[1]),
+            _ => {
+                let arguments: Vec<String> = children
+                    .iter()
+                    .cloned()
+                    .chain(self.parameters().iter().map(f64::to_string))
+                    .collect();
+                format!("{}({})", self.kind(), arguments.join(", "))
+            }
+        }
+    }
+}
+
+impl Graph {
+    /// Writes `of` as a single expression. Shared nodes are written out again every time they're
+    /// used, so this can be exponentially long; see `to_infix_shared`.
+    ///
+    /// ```
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let two = g.push(Node::Constant(2.0));
+    /// let sum = g.push(a + two);
+    /// let exp = g.push(Node::Exp { child: sum });
+    /// let product = g.push(exp * sum);
+    ///
+    /// assert_eq!("(exp((x0 + 2)) * (x0 + 2))", g.to_infix(product));
+    /// ```
+    pub fn to_infix(&self, of: Idx) -> String {
+        let children: Vec<String> = self[of]
+            .children()
+            .into_iter()
+            .map(|child| self.to_infix(child))
+            .collect();
+        self[of].to_infix(of, &children)
+    }
+
+    /// Like `to_infix`, but any node other than a leaf that's used more than once is written
+    /// once as a binding like `t0 = (x0 + x1)`, and then referred to by name. The bindings come
+    /// first, separated by semicolons, so the output grows linearly with the graph.
+    ///
+    /// ```
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Variable);
+    /// let sum = g.push(a + b);
+    /// let product = g.push(sum * sum);
+    ///
+    /// assert_eq!("t0 = (x0 + x1); (t0 * t0)", g.to_infix_shared(product));
+    /// ```
+    pub fn to_infix_shared(&self, of: Idx) -> String {
+        let subgraph = self.subgraph_for(&[of]);
+
+        let mut uses: HashMap<Idx, usize> = HashMap::new();
+        for index in subgraph.indices() {
+            for child in self[*index].children() {
+                *uses.entry(child).or_insert(0) += 1;
+            }
+        }
+
+        // What each node is called in the expressions of its parents
+        let mut references: HashMap<Idx, String> = HashMap::new();
+        let mut bindings: Vec<String> = Vec::new();
+        for &index in subgraph.indices() {
+            let node = &self[index];
+            let children: Vec<String> = node
+                .children()
+                .iter()
+                .map(|child| references[child].clone())
+                .collect();
+            let expression = node.to_infix(index, &children);
+
+            let shared = uses.get(&index).is_some_and(|&count| count > 1);
+            if shared && !children.is_empty() {
+                let name = format!("t{}", bindings.len());
+                bindings.push(format!("{} = {}", name, expression));
+                references.insert(index, name);
+            } else {
+                references.insert(index, expression);
+            }
+        }
+
+        bindings.push(references.remove(&of).expect("of is in its own subgraph"));
+        bindings.join("; ")
+    }
+}
@@ -0,0 +1,259 @@
+use std::collections::HashMap;
+
+use super::{sign, Graph, Idx, Node, ReduceOp};
+
+/// The smallest value that GELU takes, rounded down so that it's still a valid bound
+const GELU_MIN: f64 = -0.170_041;
+
+fn sorted(a: f64, b: f64) -> (f64, f64) {
+    (a.min(b), a.max(b))
+}
+
+fn multiply((a_lo, a_hi): (f64, f64), (b_lo, b_hi): (f64, f64)) -> (f64, f64) {
+    let products = [a_lo * b_lo, a_lo * b_hi, a_hi * b_lo, a_hi * b_hi];
+    (
+        products.iter().cloned().fold(f64::INFINITY, f64::min),
+        products.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+    )
+}
+
+fn log_sum_exp(values: &[f64]) -> f64 {
+    let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    if max.is_infinite() {
+        return max;
+    }
+    max + values
+        .iter()
+        .map(|value| (value - max).exp())
+        .sum::<f64>()
+        .ln()
+}
+
+fn power((lo, hi): (f64, f64), exponent: f64) -> (f64, f64) {
+    if exponent == 0.0 {
+        (1.0, 1.0)
+    } else if lo >= 0.0 {
+        sorted(lo.powf(exponent), hi.powf(exponent))
+    } else if exponent.fract() != 0.0 {
+        // Fractional powers of negative numbers aren't real
+        (f64::NAN, f64::NAN)
+    } else if exponent % 2.0 != 0.0 {
+        if exponent > 0.0 || hi < 0.0 {
+            sorted(lo.powf(exponent), hi.powf(exponent))
+        } else {
+            (f64::NEG_INFINITY, f64::INFINITY)
+        }
+    } else if hi < 0.0 {
+        sorted(lo.powf(exponent), hi.powf(exponent))
+    } else if exponent > 0.0 {
+        (0.0, lo.powf(exponent).max(hi.powf(exponent)))
+    } else {
+        (lo.powf(exponent).min(hi.powf(exponent)), f64::INFINITY)
+    }
+}
+
+impl ReduceOp {
+    fn interval(self, intervals: impl Iterator<Item = (f64, f64)>) -> (f64, f64) {
+        match self {
+            ReduceOp::Sum => intervals.fold((0.0, 0.0), |(lo, hi), (a, b)| (lo + a, hi + b)),
+            ReduceOp::Product => intervals.fold((1.0, 1.0), multiply),
+            ReduceOp::Max => intervals.fold(
+                (f64::NEG_INFINITY, f64::NEG_INFINITY),
+                |(lo, hi), (a, b)| (lo.max(a), hi.max(b)),
+            ),
+            ReduceOp::Min => intervals.fold((f64::INFINITY, f64::INFINITY), |(lo, hi), (a, b)| {
+                (lo.min(a), hi.min(b))
+            }),
+        }
+    }
+}
+
+impl Node {
+    /// The range of values that this node can take, given ranges for its children.
+    fn get_interval(&self, my_index: &Idx, intervals: &HashMap<Idx, (f64, f64)>) -> (f64, f64) {
+        let child_intervals = || self.children().into_iter().map(|child| intervals[&child]);
+
+        match self {
+            Node::Constant(value) => (*value, *value),
+            Node::VectorConstant(values) => (
+                values.iter().cloned().fold(f64::INFINITY, f64::min),
+                values.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+            ),
+            Node::Variable | Node::External { .. } => intervals[my_index],
+            Node::Sum { .. } => ReduceOp::Sum.interval(child_intervals()),
+            Node::Product { .. } => ReduceOp::Product.interval(child_intervals()),
+            Node::Max { .. } => ReduceOp::Max.interval(child_intervals()),
+            Node::Reduce { op, .. } => op.interval(child_intervals()),
+            Node::Exp { child } => (intervals[child].0.exp(), intervals[child].1.exp()),
+            Node::Ln { child } => (intervals[child].0.ln(), intervals[child].1.ln()),
+            Node::SquaredNorm { .. } => {
+                ReduceOp::Sum.interval(child_intervals().map(|interval| power(interval, 2.0)))
+            }
+            Node::LogSumExp { .. } => {
+                let (los, his): (Vec<f64>, Vec<f64>) = child_intervals().unzip();
+                (log_sum_exp(&los), log_sum_exp(&his))
+            }
+            Node::Tanh { child } => (intervals[child].0.tanh(), intervals[child].1.tanh()),
+            Node::Sinh { child } => (intervals[child].0.sinh(), intervals[child].1.sinh()),
+            Node::Cosh { child } => {
+                let (lo, hi) = intervals[child];
+                let max = lo.cosh().max(hi.cosh());
+                if lo <= 0.0 && 0.0 <= hi {
+                    (1.0, max)
+                } else {
+                    (lo.cosh().min(hi.cosh()), max)
+                }
+            }
+            Node::Atan { child } => (intervals[child].0.atan(), intervals[child].1.atan()),
+            Node::Asin { child } => (intervals[child].0.asin(), intervals[child].1.asin()),
+            Node::Softplus { child } => {
+                let softplus = |x: f64| x.max(0.0) + (-x.abs()).exp().ln_1p();
+                (softplus(intervals[child].0), softplus(intervals[child].1))
+            }
+            Node::Pow { child, exponent } => power(intervals[child], *exponent),
+            Node::Identity { child } | Node::Memo { child } | Node::Reshape { child, .. } => {
+                intervals[child]
+            }
+            Node::Gelu { child } => {
+                let gelu = |x: f64| {
+                    let mut values = HashMap::new();
+                    values.insert(*child, x);
+                    self.get_value(my_index, &values)
+                };
+                let (lo, hi) = intervals[child];
+                let (gelu_lo, gelu_hi) = sorted(gelu(lo), gelu(hi));
+                // GELU dips below zero for negative inputs before coming back up
+                if lo < 0.0 {
+                    (gelu_lo.min(GELU_MIN), gelu_hi)
+                } else {
+                    (gelu_lo, gelu_hi)
+                }
+            }
+            Node::Ewma { input, prev, alpha } => {
+                let (input_lo, input_hi) =
+                    sorted(alpha * intervals[input].0, alpha * intervals[input].1);
+                let (prev_lo, prev_hi) = sorted(
+                    (1.0 - alpha) * intervals[prev].0,
+                    (1.0 - alpha) * intervals[prev].1,
+                );
+                (input_lo + prev_lo, input_hi + prev_hi)
+            }
+            Node::SafeDiv {
+                numerator,
+                denominator,
+                default,
+            } => {
+                let (d_lo, d_hi) = intervals[denominator];
+                if d_lo > 0.0 || d_hi < 0.0 {
+                    multiply(intervals[numerator], sorted(1.0 / d_lo, 1.0 / d_hi))
+                } else if d_lo == 0.0 && d_hi == 0.0 {
+                    (*default, *default)
+                } else {
+                    // Dividing by values close to zero can give anything
+                    (f64::NEG_INFINITY, f64::INFINITY)
+                }
+            }
+            Node::Modulo { b, .. } => {
+                let (b_lo, b_hi) = intervals[b];
+                (0.0, b_lo.abs().max(b_hi.abs()))
+            }
+            Node::Select {
+                condition,
+                if_true,
+                if_false,
+            } => {
+                let (condition_lo, condition_hi) = intervals[condition];
+                if condition_lo > 0.0 {
+                    intervals[if_true]
+                } else if condition_hi <= 0.0 {
+                    intervals[if_false]
+                } else {
+                    let (true_lo, true_hi) = intervals[if_true];
+                    let (false_lo, false_hi) = intervals[if_false];
+                    (true_lo.min(false_lo), true_hi.max(false_hi))
+                }
+            }
+            Node::Scale { child, factor } => {
+                sorted(factor * intervals[child].0, factor * intervals[child].1)
+            }
+            Node::Greater { a, b } => {
+                let ((a_lo, a_hi), (b_lo, b_hi)) = (intervals[a], intervals[b]);
+                if a_lo > b_hi {
+                    (1.0, 1.0)
+                } else if a_hi <= b_lo {
+                    (0.0, 0.0)
+                } else {
+                    (0.0, 1.0)
+                }
+            }
+            Node::Equal { a, b } => {
+                let ((a_lo, a_hi), (b_lo, b_hi)) = (intervals[a], intervals[b]);
+                if a_lo == a_hi && b_lo == b_hi && a_lo == b_lo {
+                    (1.0, 1.0)
+                } else if a_hi < b_lo || b_hi < a_lo {
+                    (0.0, 0.0)
+                } else {
+                    (0.0, 1.0)
+                }
+            }
+            Node::Bucketize { child, edges } => {
+                let bucket = |value: f64| edges.iter().filter(|&&edge| edge <= value).count();
+                let (lo, hi) = intervals[child];
+                (bucket(lo) as f64, bucket(hi) as f64)
+            }
+            // Sign never decreases
+            Node::Sign { child } => {
+                let (lo, hi) = intervals[child];
+                (sign(lo), sign(hi))
+            }
+            // An element of a unit vector can be anywhere between zero and ±1, on the input's side
+            Node::Normalize { child } => {
+                let (lo, hi) = intervals[child];
+                (sign(lo).min(0.0), sign(hi).max(0.0))
+            }
+            // The sign of a negative base depends on the exponent
+            Node::PowNode { .. } => (f64::NEG_INFINITY, f64::INFINITY),
+            // Each element sums a row of products, and the length of the row isn't known here
+            Node::MatMul { .. } => (f64::NEG_INFINITY, f64::INFINITY),
+            // Every value comes from between two of the ys
+            Node::Lerp { ys, .. } => (
+                ys.iter().cloned().fold(f64::INFINITY, f64::min),
+                ys.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+            ),
+            // Nothing is known about the closure
+            Node::Custom { .. } => (f64::NEG_INFINITY, f64::INFINITY),
+        }
+    }
+}
+
+impl Graph {
+    /// Given a `(lo, hi)` range for each variable, finds a range that each node's value is
+    /// guaranteed to fall within. The ranges are conservative, so they can be wider than the
+    /// values that are actually possible.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Variable);
+    /// let sum = g.push(a + b);
+    /// let product = g.push(a * b);
+    ///
+    /// let ranges: HashMap<_, _> = vec![(a, (1.0, 2.0)), (b, (3.0, 4.0))].into_iter().collect();
+    /// let intervals = g.evaluate_intervals(ranges);
+    /// assert_eq!((4.0, 6.0), intervals[&sum]);
+    /// assert_eq!((3.0, 8.0), intervals[&product]);
+    /// ```
+    pub fn evaluate_intervals(&self, ranges: HashMap<Idx, (f64, f64)>) -> HashMap<Idx, (f64, f64)> {
+        let mut result = ranges;
+
+        for index in self.as_subgraph().indices() {
+            let interval = self[*index].get_interval(index, &result);
+            result.insert(*index, interval);
+        }
+
+        result
+    }
+}
@@ -0,0 +1,121 @@
+use std::collections::{HashMap, HashSet};
+
+use super::{Bindings, Graph, Idx, Node};
+
+/// The values of `Memo` nodes from earlier evaluations. Each value is stored along with the
+/// values of the variables that it was computed from.
+#[derive(Clone, Debug, Default)]
+pub struct MemoCache {
+    values: HashMap<(Idx, Vec<Option<u64>>), f64>,
+}
+
+impl MemoCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+impl Graph {
+    /// Evaluates the graph, except that each `Memo` node whose variables have the same values
+    /// as in an earlier evaluation with the same `cache` takes its value from the cache. The
+    /// nodes under a cached memo aren't evaluated at all, so they're left out of the result
+    /// unless something else needs them.
+    ///
+    /// ```
+    /// use std::cell::Cell;
+    /// use std::collections::HashMap;
+    /// use std::rc::Rc;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, MemoCache, Node};
+    ///
+    /// let calls = Rc::new(Cell::new(0));
+    /// let counter = calls.clone();
+    ///
+    /// let mut g = Graph::default();
+    /// let x = g.push(Node::Variable);
+    /// let y = g.push(Node::Variable);
+    /// let expensive = g.push(Node::custom(
+    ///     vec![x],
+    ///     move |inputs| {
+    ///         counter.set(counter.get() + 1);
+    ///         inputs[0].exp()
+    ///     },
+    ///     |inputs| vec![inputs[0].exp()],
+    /// ));
+    /// let memo = g.push(Node::Memo { child: expensive });
+    /// let output = g.push(memo + y);
+    ///
+    /// let at = |x_value: f64, y_value: f64| -> HashMap<_, _> {
+    ///     vec![(x, x_value), (y, y_value)].into_iter().collect()
+    /// };
+    /// let mut cache = MemoCache::new();
+    /// assert_eq!(1.0, g.evaluate_with_memos(at(0.0, 0.0), &mut cache)[&output]);
+    /// assert_eq!(1, calls.get());
+    ///
+    /// // Only y changed, which the memo doesn't depend on
+    /// assert_eq!(6.0, g.evaluate_with_memos(at(0.0, 5.0), &mut cache)[&output]);
+    /// assert_eq!(1, calls.get());
+    ///
+    /// g.evaluate_with_memos(at(1.0, 5.0), &mut cache);
+    /// assert_eq!(2, calls.get());
+    /// assert_eq!(2, cache.len());
+    /// ```
+    pub fn evaluate_with_memos(
+        &self,
+        values: impl Into<Bindings>,
+        cache: &mut MemoCache,
+    ) -> HashMap<Idx, f64> {
+        let mut result = values.into().into_map();
+
+        // Look up every memo first, so that nothing under a cached one gets evaluated
+        let mut keys: HashMap<Idx, Vec<Option<u64>>> = HashMap::new();
+        let mut cached: HashMap<Idx, f64> = HashMap::new();
+        for (i, node) in self.nodes.iter().enumerate() {
+            if let Node::Memo { child } = *node {
+                let mut variables: Vec<Idx> = self.relevant_variables(child).into_iter().collect();
+                variables.sort_by_key(|variable| variable.0);
+                let key: Vec<Option<u64>> = variables
+                    .iter()
+                    .map(|variable| result.get(variable).map(|value| value.to_bits()))
+                    .collect();
+                if let Some(&value) = cache.values.get(&(Idx(i), key.clone())) {
+                    cached.insert(Idx(i), value);
+                }
+                keys.insert(Idx(i), key);
+            }
+        }
+
+        // Everything the roots need, stopping at cached memos
+        let mut needed: HashSet<Idx> = HashSet::new();
+        let mut stack = self.roots();
+        while let Some(index) = stack.pop() {
+            if needed.insert(index) && !cached.contains_key(&index) {
+                stack.extend(self[index].children());
+            }
+        }
+
+        for i in 0..self.len() {
+            let index = Idx(i);
+            if !needed.contains(&index) {
+                continue;
+            }
+            let value = match cached.get(&index) {
+                Some(&value) => value,
+                None => self[index].get_value(&index, &result),
+            };
+            if let Some(key) = keys.remove(&index) {
+                cache.values.insert((index, key), value);
+            }
+            result.insert(index, value);
+        }
+
+        result
+    }
+}
@@ -0,0 +1,66 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use super::{Graph, Idx};
+
+/// Annotations attached to nodes, at most one of each type per node. Metadata doesn't affect
+/// what a graph computes, so it's ignored when comparing graphs.
+#[derive(Clone, Default)]
+pub(crate) struct Metadata {
+    values: HashMap<(Idx, TypeId), Rc<dyn Any>>,
+}
+
+impl Metadata {
+    /// Drops the metadata for every node from `len` onwards.
+    pub(crate) fn truncate(&mut self, len: usize) {
+        self.values.retain(|&(idx, _), _| idx.0 < len);
+    }
+}
+
+impl fmt::Debug for Metadata {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Metadata({} entries)", self.values.len())
+    }
+}
+
+impl PartialEq for Metadata {
+    fn eq(&self, _other: &Metadata) -> bool {
+        true
+    }
+}
+
+impl Graph {
+    /// Attaches a value of any type to a node, replacing any earlier value of the same type.
+    /// This is a place for domain-specific annotations like units, shapes, or provenance.
+    ///
+    /// ```
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// #[derive(Debug, PartialEq)]
+    /// struct Units(&'static str);
+    ///
+    /// let mut g = Graph::default();
+    /// let distance = g.push(Node::Variable);
+    /// let time = g.push(Node::Variable);
+    /// g.set_meta(distance, Units("m"));
+    ///
+    /// assert_eq!(Some(&Units("m")), g.get_meta::<Units>(distance));
+    /// assert_eq!(None, g.get_meta::<Units>(time));
+    /// assert_eq!(None, g.get_meta::<String>(distance));
+    /// ```
+    pub fn set_meta<T: Any>(&mut self, idx: Idx, value: T) {
+        self.metadata
+            .values
+            .insert((idx, TypeId::of::<T>()), Rc::new(value));
+    }
+
+    /// The value of type `T` that was attached to the node, if there is one.
+    pub fn get_meta<T: Any>(&self, idx: Idx) -> Option<&T> {
+        self.metadata
+            .values
+            .get(&(idx, TypeId::of::<T>()))
+            .and_then(|value| value.downcast_ref())
+    }
+}
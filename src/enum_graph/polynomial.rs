@@ -0,0 +1,86 @@
+use super::{Graph, Idx, Node, ReduceOp};
+
+/// The coefficients of a polynomial, lowest power first
+type Coefficients = Vec<f64>;
+
+fn add(a: &[f64], b: &[f64]) -> Coefficients {
+    (0..a.len().max(b.len()))
+        .map(|i| a.get(i).unwrap_or(&0.0) + b.get(i).unwrap_or(&0.0))
+        .collect()
+}
+
+fn multiply(a: &[f64], b: &[f64]) -> Coefficients {
+    let mut product = vec![0.0; a.len() + b.len() - 1];
+    for (i, x) in a.iter().enumerate() {
+        for (j, y) in b.iter().enumerate() {
+            product[i + j] += x * y;
+        }
+    }
+    product
+}
+
+impl Graph {
+    /// Writes `output` as a polynomial in `var`, returning its coefficients from the constant
+    /// term up, without trailing zeros. This only works if everything `output` depends on is a
+    /// `Constant`, `var` itself, a sum or product (either dedicated or as a `Reduce`), or a
+    /// `Scale` or `Pow` with a non-negative integer exponent; otherwise this returns `None`.
+    ///
+    /// ```
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// // x * x + 2 * x + 1
+    /// let mut g = Graph::default();
+    /// let x = g.push(Node::Variable);
+    /// let square = g.push(x * x);
+    /// let two = g.push(Node::Constant(2.0));
+    /// let linear = g.push(two * x);
+    /// let one = g.push(Node::Constant(1.0));
+    /// let output = g.push(Node::Sum { children: vec![square, linear, one] });
+    /// assert_eq!(Some(vec![1.0, 2.0, 1.0]), g.as_polynomial(output, x));
+    ///
+    /// let exp = g.push(Node::Exp { child: x });
+    /// assert_eq!(None, g.as_polynomial(exp, x));
+    /// ```
+    pub fn as_polynomial(&self, output: Idx, var: Idx) -> Option<Vec<f64>> {
+        let mut polynomials: Vec<Option<Coefficients>> = vec![None; self.len()];
+
+        for &index in self.subgraph_for(&[output]).indices() {
+            let polynomial = match self[index].as_reduce() {
+                Some((ReduceOp::Sum, children)) => {
+                    children.iter().try_fold(vec![0.0], |total, child| {
+                        polynomials[child.0].as_ref().map(|p| add(&total, p))
+                    })?
+                }
+                Some((ReduceOp::Product, children)) => {
+                    children.iter().try_fold(vec![1.0], |total, child| {
+                        polynomials[child.0].as_ref().map(|p| multiply(&total, p))
+                    })?
+                }
+                Some(_) => return None,
+                None => match &self[index] {
+                    Node::Constant(value) => vec![*value],
+                    Node::Variable if index == var => vec![0.0, 1.0],
+                    Node::Scale { child, factor } => polynomials[child.0]
+                        .as_ref()?
+                        .iter()
+                        .map(|coefficient| coefficient * factor)
+                        .collect(),
+                    Node::Pow { child, exponent }
+                        if *exponent >= 0.0 && exponent.fract() == 0.0 =>
+                    {
+                        let base = polynomials[child.0].as_ref()?;
+                        (0..*exponent as usize).fold(vec![1.0], |total, _| multiply(&total, base))
+                    }
+                    _ => return None,
+                },
+            };
+            polynomials[index.0] = Some(polynomial);
+        }
+
+        let mut coefficients = polynomials[output.0].take()?;
+        while coefficients.len() > 1 && coefficients.last() == Some(&0.0) {
+            coefficients.pop();
+        }
+        Some(coefficients)
+    }
+}
@@ -0,0 +1,147 @@
+use std::ops::Index;
+
+use super::{Graph, Idx, Node, ReduceOp};
+
+/// One step of a `Program`. Each instruction computes the value of one node, which goes into
+/// the next slot, so arguments refer to the slots of earlier instructions.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Instruction {
+    PushConst(f64),
+    /// Reads the variable at this position in the inputs to `Program::run`
+    LoadVar(usize),
+    Add(Vec<usize>),
+    Mul(Vec<usize>),
+    Scale(usize, f64),
+    Exp(usize),
+    /// Any other node, with its children referring to slots. This reads the children straight
+    /// from the slots too, but goes through the same match over node kinds as `evaluate`.
+    Node(Node),
+}
+
+/// Lets `Node::get_value` read children from the slots, treating each `Idx` as a slot.
+struct Slots<'a>(&'a [f64]);
+
+impl<'a, 'b> Index<&'b Idx> for Slots<'a> {
+    type Output = f64;
+
+    fn index(&self, idx: &'b Idx) -> &f64 {
+        &self.0[idx.0]
+    }
+}
+
+/// A graph flattened into a list of instructions, which can be run repeatedly without going
+/// back to the graph.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Program {
+    pub instructions: Vec<Instruction>,
+}
+
+impl Graph {
+    /// Compiles the graph into a `Program` with one instruction per node. Variables are
+    /// numbered in index order.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Instruction, Node};
+    ///
+    /// // exp(a * b) + 2 * a
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Variable);
+    /// let product = g.push(a * b);
+    /// let exp = g.push(Node::Exp { child: product });
+    /// let double = g.push(a * 2.0);
+    /// let sum = g.push(exp + double);
+    ///
+    /// let program = g.compile();
+    /// assert_eq!(Instruction::LoadVar(1), program.instructions[1]);
+    /// assert_eq!(Instruction::Add(vec![3, 4]), program.instructions[5]);
+    /// // Every node here has its own instruction
+    /// assert!(!program.instructions.iter().any(|i| matches!(i, Instruction::Node(_))));
+    ///
+    /// let nodes = [a, b, product, exp, double, sum];
+    /// for &(a_value, b_value) in &[(0.5, 2.0), (-1.0, 3.0)] {
+    ///     let values: HashMap<_, _> = vec![(a, a_value), (b, b_value)].into_iter().collect();
+    ///     let expected = g.evaluate(values);
+    ///     let result = program.run(&[a_value, b_value]);
+    ///     for (node, value) in nodes.iter().zip(result) {
+    ///         assert_eq!(expected[node], value);
+    ///     }
+    /// }
+    /// ```
+    pub fn compile(&self) -> Program {
+        let mut variable_count = 0;
+        let slots = |children: &[Idx]| children.iter().map(|child| child.0).collect();
+
+        let instructions = self
+            .nodes
+            .iter()
+            .map(|node| match node {
+                Node::Constant(value) => Instruction::PushConst(*value),
+                Node::Variable => {
+                    variable_count += 1;
+                    Instruction::LoadVar(variable_count - 1)
+                }
+                Node::Sum { children }
+                | Node::Reduce {
+                    op: ReduceOp::Sum,
+                    children,
+                } => Instruction::Add(slots(children)),
+                Node::Product { children }
+                | Node::Reduce {
+                    op: ReduceOp::Product,
+                    children,
+                } => Instruction::Mul(slots(children)),
+                Node::Scale { child, factor } => Instruction::Scale(child.0, *factor),
+                Node::Exp { child } => Instruction::Exp(child.0),
+                _ => Instruction::Node(node.clone()),
+            })
+            .collect();
+
+        Program { instructions }
+    }
+}
+
+impl Program {
+    /// Runs the program with one value per variable, returning the value of every slot.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Instruction, Node};
+    ///
+    /// // max(tanh(a), b)
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Variable);
+    /// let tanh = g.push(Node::Tanh { child: a });
+    /// let max = g.push(Node::Max { children: vec![tanh, b] });
+    ///
+    /// let program = g.compile();
+    /// assert_eq!(Instruction::Node(Node::Tanh { child: a }), program.instructions[2]);
+    /// let values: HashMap<_, _> = vec![(a, 2.0), (b, 0.5)].into_iter().collect();
+    /// assert_eq!(g.evaluate(values)[&max], program.run(&[2.0, 0.5])[3]);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are too few values, or if the program contains `External`,
+    /// `VectorConstant`, `Normalize`, or `MatMul` nodes.
+    pub fn run(&self, vars: &[f64]) -> Vec<f64> {
+        let mut slots: Vec<f64> = Vec::with_capacity(self.instructions.len());
+
+        for (i, instruction) in self.instructions.iter().enumerate() {
+            let value = match instruction {
+                Instruction::PushConst(value) => *value,
+                Instruction::LoadVar(position) => vars[*position],
+                Instruction::Add(arguments) => arguments.iter().map(|&slot| slots[slot]).sum(),
+                Instruction::Mul(arguments) => arguments.iter().map(|&slot| slots[slot]).product(),
+                Instruction::Scale(slot, factor) => factor * slots[*slot],
+                Instruction::Exp(slot) => slots[*slot].exp(),
+                Instruction::Node(node) => node.get_value(&Idx(i), &Slots(&slots)),
+            };
+            slots.push(value);
+        }
+
+        slots
+    }
+}
@@ -0,0 +1,63 @@
+use super::{Graph, Idx, Node};
+
+impl Graph {
+    /// Generates a random graph with `n` nodes, for benchmarking evaluation and traversals. Every
+    /// node only picks children from earlier indices, so the result is always a valid DAG.
+    ///
+    /// This crate doesn't depend on `rand`, so the randomness comes from `rng`, which should
+    /// return a fresh random `u64` each time it's called. Something like
+    /// `|| rng.gen::<u64>()` adapts a `rand` generator.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// // A tiny xorshift generator is plenty for this
+    /// let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    /// let mut rng = || {
+    ///     state ^= state << 13;
+    ///     state ^= state >> 7;
+    ///     state ^= state << 17;
+    ///     state
+    /// };
+    ///
+    /// let g = Graph::random_dag(&mut rng, 100, 3);
+    /// assert_eq!(100, g.len());
+    /// assert_eq!(Ok(()), g.validate());
+    ///
+    /// let values: HashMap<_, _> = g
+    ///     .leaves()
+    ///     .filter(|&i| g[i] == Node::Variable)
+    ///     .map(|i| (i, 0.5))
+    ///     .collect();
+    /// assert_eq!(100, g.evaluate(values).len());
+    /// ```
+    // u64::is_multiple_of needs Rust 1.87, which is much newer than anything else here needs
+    #[allow(clippy::manual_is_multiple_of)]
+    pub fn random_dag(rng: &mut impl FnMut() -> u64, n: usize, max_children: usize) -> Graph {
+        let mut graph = Graph::default();
+
+        for i in 0..n {
+            let node = if i == 0 || max_children == 0 || rng() % 4 == 0 {
+                if rng() % 2 == 0 {
+                    Node::Variable
+                } else {
+                    Node::Constant((rng() % 100) as f64 / 100.0)
+                }
+            } else {
+                let count = 1 + (rng() % max_children as u64) as usize;
+                let children = (0..count)
+                    .map(|_| Idx((rng() % i as u64) as usize))
+                    .collect();
+                if rng() % 2 == 0 {
+                    Node::Sum { children }
+                } else {
+                    Node::Product { children }
+                }
+            };
+            graph.push(node);
+        }
+
+        graph
+    }
+}
@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::ops::{Add, Mul};
+
+use super::{Graph, GraphError, Idx, Scalar};
+
+/// An exact fraction of two `i64`s, always stored in lowest terms with a positive denominator.
+///
+/// The arithmetic never silently wraps. If a numerator or denominator doesn't fit in an `i64`,
+/// even after reducing, `checked_new`, `checked_add`, and `checked_mul` return `None`, and
+/// `new`, `+`, and `*` panic. For example, `-1 / i64::MIN` is `1 / 2^63`, and `2^63` doesn't
+/// fit.
+///
+/// ```
+/// use exploring_computation_graphs_in_rust::enum_graph::Rational;
+///
+/// assert_eq!(Rational::new(1, 2), Rational::new(2, 4));
+/// assert_eq!(Rational::new(5, 6), Rational::new(1, 2) + Rational::new(1, 3));
+/// assert_eq!(Rational::new(-1, 6), Rational::new(1, -2) * Rational::new(1, 3));
+///
+/// assert_eq!(None, Rational::checked_new(-1, i64::MIN));
+/// assert_eq!(Some(Rational::new(1, 2)), Rational::checked_new(i64::MIN / 2, i64::MIN));
+/// assert_eq!(i64::MIN, Rational::integer(i64::MIN).numerator());
+/// assert_eq!(None, Rational::integer(i64::MIN).checked_mul(Rational::integer(-1)));
+/// assert_eq!(None, Rational::integer(i64::MAX).checked_add(Rational::integer(1)));
+/// ```
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Rational {
+    numerator: i64,
+    denominator: i64,
+}
+
+/// Unsigned, so that there's no overflow taking the absolute value of `i64::MIN`
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+impl Rational {
+    /// # Panics
+    ///
+    /// Panics if `denominator` is zero, or if the fraction doesn't fit in lowest terms.
+    pub fn new(numerator: i64, denominator: i64) -> Self {
+        assert!(denominator != 0, "a rational can't have a zero denominator");
+        Self::checked_new(numerator, denominator)
+            .unwrap_or_else(|| panic!("overflow normalizing {} / {}", numerator, denominator))
+    }
+
+    /// Like `new`, but returns `None` instead of panicking.
+    pub fn checked_new(numerator: i64, denominator: i64) -> Option<Self> {
+        if denominator == 0 {
+            return None;
+        }
+        // The divisor is 2^63 when both are multiples of i64::MIN, so divide in i128
+        let divisor = i128::from(gcd(numerator.unsigned_abs(), denominator.unsigned_abs()));
+        let sign = i128::from(denominator.signum());
+        let numerator = sign * i128::from(numerator) / divisor;
+        let denominator = sign * i128::from(denominator) / divisor;
+        Some(Self {
+            numerator: i64::try_from(numerator).ok()?,
+            denominator: i64::try_from(denominator).ok()?,
+        })
+    }
+
+    pub fn integer(value: i64) -> Self {
+        Self::new(value, 1)
+    }
+
+    pub fn numerator(self) -> i64 {
+        self.numerator
+    }
+
+    pub fn denominator(self) -> i64 {
+        self.denominator
+    }
+
+    /// The closest `f64`, for comparing with the floating-point evaluators.
+    pub fn to_f64(self) -> f64 {
+        self.numerator as f64 / self.denominator as f64
+    }
+
+    /// The sum, or `None` if it doesn't fit.
+    pub fn checked_add(self, rhs: Rational) -> Option<Rational> {
+        // Denominators are positive, so the divisor fits in an i64
+        let divisor = gcd(self.denominator as u64, rhs.denominator as u64) as i64;
+        let left = self.numerator.checked_mul(rhs.denominator / divisor)?;
+        let right = rhs.numerator.checked_mul(self.denominator / divisor)?;
+        let denominator = (self.denominator / divisor).checked_mul(rhs.denominator)?;
+        Rational::checked_new(left.checked_add(right)?, denominator)
+    }
+
+    /// The product, or `None` if it doesn't fit.
+    pub fn checked_mul(self, rhs: Rational) -> Option<Rational> {
+        // Cancel across the two fractions first so that the products stay small. Each gcd
+        // divides a positive denominator, so it fits in an i64.
+        let a = gcd(self.numerator.unsigned_abs(), rhs.denominator as u64).max(1) as i64;
+        let b = gcd(rhs.numerator.unsigned_abs(), self.denominator as u64).max(1) as i64;
+        let numerator = (self.numerator / a).checked_mul(rhs.numerator / b)?;
+        let denominator = (self.denominator / b).checked_mul(rhs.denominator / a)?;
+        Rational::checked_new(numerator, denominator)
+    }
+}
+
+impl Add for Rational {
+    type Output = Rational;
+
+    fn add(self, rhs: Rational) -> Rational {
+        self.checked_add(rhs)
+            .unwrap_or_else(|| panic!("overflow adding {:?} and {:?}", self, rhs))
+    }
+}
+
+impl Mul for Rational {
+    type Output = Rational;
+
+    fn mul(self, rhs: Rational) -> Rational {
+        self.checked_mul(rhs)
+            .unwrap_or_else(|| panic!("overflow multiplying {:?} and {:?}", self, rhs))
+    }
+}
+
+impl Scalar for Rational {
+    fn zero() -> Self {
+        Rational::integer(0)
+    }
+
+    fn one() -> Self {
+        Rational::integer(1)
+    }
+
+    fn checked_add(self, rhs: Self) -> Option<Self> {
+        Rational::checked_add(self, rhs)
+    }
+
+    fn checked_mul(self, rhs: Self) -> Option<Self> {
+        Rational::checked_mul(self, rhs)
+    }
+
+    /// Only integers, so that constants are exact
+    fn from_f64(value: f64) -> Option<Self> {
+        if value.fract() == 0.0 && value.abs() < i64::MAX as f64 {
+            Some(Rational::integer(value as i64))
+        } else {
+            None
+        }
+    }
+
+    fn unsupported(idx: Idx) -> GraphError {
+        GraphError::NotRational(idx)
+    }
+}
+
+impl Graph {
+    /// Evaluates the graph exactly, with a `Rational` for each variable. This is
+    /// `evaluate_scalar`: only constants with integer values, variables, and sums, products, and
+    /// integer scales of them can be evaluated this way, and anything else returns
+    /// `GraphError::NotRational`. If a value doesn't fit in a `Rational`, this returns
+    /// `GraphError::Overflow`.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, GraphError, Node, Rational};
+    ///
+    /// let mut g = Graph::default();
+    /// let thirds: Vec<_> = (0..3).map(|_| g.push(Node::Variable)).collect();
+    /// let sum = g.push(Node::Sum { children: thirds.clone() });
+    ///
+    /// let values: HashMap<_, _> = thirds.iter().map(|&x| (x, Rational::new(1, 3))).collect();
+    /// assert_eq!(Rational::integer(1), g.evaluate_rational(values).unwrap()[&sum]);
+    ///
+    /// let values: HashMap<_, _> =
+    ///     thirds.iter().map(|&x| (x, Rational::integer(i64::MIN))).collect();
+    /// assert_eq!(Err(GraphError::Overflow(sum)), g.evaluate_rational(values));
+    /// ```
+    pub fn evaluate_rational(
+        &self,
+        values: HashMap<Idx, Rational>,
+    ) -> Result<HashMap<Idx, Rational>, GraphError> {
+        self.evaluate_scalar(values)
+    }
+}
@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+use std::ops::Index;
+
+use super::{Graph, Idx, Node};
+
+/// An operation that combines any number of children into one value. `Sum`, `Product`, and
+/// `Max` nodes are shorthand for the matching `Node::Reduce`, and all of them share the code
+/// here.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ReduceOp {
+    Sum,
+    Product,
+    Max,
+    Min,
+}
+
+impl ReduceOp {
+    pub fn name(self) -> &'static str {
+        match self {
+            ReduceOp::Sum => "sum",
+            ReduceOp::Product => "product",
+            ReduceOp::Max => "max",
+            ReduceOp::Min => "min",
+        }
+    }
+
+    pub(crate) fn from_name(name: &str) -> Option<ReduceOp> {
+        [
+            ReduceOp::Sum,
+            ReduceOp::Product,
+            ReduceOp::Max,
+            ReduceOp::Min,
+        ]
+        .iter()
+        .cloned()
+        .find(|op| op.name() == name)
+    }
+
+    /// The node that applies this op to `children`. This uses the dedicated variant when
+    /// there is one.
+    pub fn node(self, children: Vec<Idx>) -> Node {
+        match self {
+            ReduceOp::Sum => Node::Sum { children },
+            ReduceOp::Product => Node::Product { children },
+            ReduceOp::Max => Node::Max { children },
+            ReduceOp::Min => Node::Reduce { op: self, children },
+        }
+    }
+
+    /// Combines a sequence of values. An empty sum is 0, an empty product is 1, and an empty
+    /// max or min is negative or positive infinity.
+    pub fn apply(self, values: impl Iterator<Item = f64>) -> f64 {
+        match self {
+            ReduceOp::Sum => values.sum(),
+            ReduceOp::Product => values.product(),
+            ReduceOp::Max => values.fold(f64::NEG_INFINITY, f64::max),
+            ReduceOp::Min => values.fold(f64::INFINITY, f64::min),
+        }
+    }
+
+    pub(crate) fn value<V>(self, children: &[Idx], values: &V) -> f64
+    where
+        V: for<'a> Index<&'a Idx, Output = f64>,
+    {
+        self.apply(children.iter().map(|child| values[child]))
+    }
+
+    pub(crate) fn local_gradients(
+        self,
+        children: &[Idx],
+        values: &HashMap<Idx, f64>,
+        my_value: f64,
+    ) -> Vec<f64> {
+        match self {
+            ReduceOp::Sum => vec![1.0; children.len()],
+            ReduceOp::Product => {
+                // The product of everything except child i is prefix[i] * suffix[i + 1]. This
+                // avoids dividing by child i, which could be zero.
+                let child_values: Vec<f64> = children.iter().map(|child| values[child]).collect();
+                let mut prefix = vec![1.0; child_values.len() + 1];
+                let mut suffix = vec![1.0; child_values.len() + 1];
+                for (i, value) in child_values.iter().enumerate() {
+                    prefix[i + 1] = prefix[i] * value;
+                }
+                for (i, value) in child_values.iter().enumerate().rev() {
+                    suffix[i] = suffix[i + 1] * value;
+                }
+                (0..child_values.len())
+                    .map(|i| prefix[i] * suffix[i + 1])
+                    .collect()
+            }
+            ReduceOp::Max | ReduceOp::Min => {
+                // All of the gradient goes to the first child that attains the extreme
+                let position = children.iter().position(|child| values[child] == my_value);
+                (0..children.len())
+                    .map(|i| if Some(i) == position { 1.0 } else { 0.0 })
+                    .collect()
+            }
+        }
+    }
+
+    pub(crate) fn derivative(
+        self,
+        children: &[Idx],
+        derivatives: &HashMap<Idx, Idx>,
+        graph: &mut Graph,
+    ) -> Idx {
+        match self {
+            ReduceOp::Sum => graph.push(Node::Sum {
+                children: children.iter().map(|child| derivatives[child]).collect(),
+            }),
+            ReduceOp::Product => {
+                let child_derivatives: Vec<Option<Idx>> = children
+                    .iter()
+                    .map(|child| Some(derivatives[child]))
+                    .collect();
+                let terms = product_rule_terms(children, &child_derivatives, graph);
+                graph.push(Node::Sum { children: terms })
+            }
+            ReduceOp::Max | ReduceOp::Min => {
+                // Walk through the children, keeping the derivative of the most extreme one so
+                // far. A later child only takes over if it's strictly more extreme, matching
+                // reverse mode.
+                let (first, rest) = match children.split_first() {
+                    Some(split) => split,
+                    None => return graph.push(Node::Constant(0.0)),
+                };
+                let sign = if self == ReduceOp::Max { 1.0 } else { -1.0 };
+                let mut best = *first;
+                let mut best_derivative = derivatives[first];
+                for child in rest {
+                    let minus_best = graph.push(best * -1.0);
+                    let difference = graph.push(*child + minus_best);
+                    let margin = graph.push(difference * sign);
+                    best_derivative = graph.push(Node::Select {
+                        condition: margin,
+                        if_true: derivatives[child],
+                        if_false: best_derivative,
+                    });
+                    best = graph.push(self.node(vec![best, *child]));
+                }
+                best_derivative
+            }
+        }
+    }
+
+    /// Whether the reduction is provably zero, given which children are.
+    pub(crate) fn is_zero(self, mut child_is_zero: impl Iterator<Item = bool>) -> bool {
+        match self {
+            ReduceOp::Sum => child_is_zero.all(|zero| zero),
+            ReduceOp::Product => child_is_zero.any(|zero| zero),
+            ReduceOp::Max | ReduceOp::Min => {
+                let mut any = false;
+                child_is_zero.all(|zero| {
+                    any = true;
+                    zero
+                }) && any
+            }
+        }
+    }
+}
+
+impl Node {
+    /// The op and children of a reduction, whether it's written with a dedicated variant like
+    /// `Sum` or as a `Reduce`, so that passes can treat both the same way.
+    ///
+    /// ```
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node, ReduceOp, Rule};
+    ///
+    /// // x * x + 2 * x + 1, once with the dedicated variants and once with Reduce
+    /// let build = |sum: &dyn Fn(Vec<_>) -> Node, product: &dyn Fn(Vec<_>) -> Node| {
+    ///     let mut g = Graph::default();
+    ///     let x = g.push(Node::Variable);
+    ///     let square = g.push(product(vec![x, x]));
+    ///     let linear = g.push(x * 2.0);
+    ///     let one = g.push(Node::Constant(1.0));
+    ///     let zero = g.push(Node::Constant(0.0));
+    ///     let output = g.push(sum(vec![square, linear, one, zero]));
+    ///     (g, x, square, linear, one, output)
+    /// };
+    /// let (dedicated, x, square, linear, one, output) =
+    ///     build(&|children| Node::Sum { children }, &|children| Node::Product { children });
+    /// let (reduce, ..) = build(
+    ///     &|children| Node::Reduce { op: ReduceOp::Sum, children },
+    ///     &|children| Node::Reduce { op: ReduceOp::Product, children },
+    /// );
+    ///
+    /// assert_eq!(Some((ReduceOp::Sum, &[x, x][..])), Node::Sum { children: vec![x, x] }.as_reduce());
+    /// assert_eq!(dedicated.as_polynomial(output, x), reduce.as_polynomial(output, x));
+    /// // The rules drop the `+ 0` from both
+    /// let (rewritten, rewritten_reduce) =
+    ///     (dedicated.apply_rules(&Rule::algebraic()), reduce.apply_rules(&Rule::algebraic()));
+    /// assert_eq!(rewritten.len(), rewritten_reduce.len());
+    /// assert_eq!(rewritten[output], Node::Sum { children: vec![square, linear, one] });
+    /// assert_eq!(dedicated.compile(), reduce.compile());
+    /// let batch = [[0.0, 1.0, 2.0, 3.0, -1.0, -2.0, 0.5, 10.0]];
+    /// assert_eq!(dedicated.evaluate_simd(&batch), reduce.evaluate_simd(&batch));
+    /// ```
+    pub fn as_reduce(&self) -> Option<(ReduceOp, &[Idx])> {
+        match self {
+            Node::Sum { children } => Some((ReduceOp::Sum, children)),
+            Node::Product { children } => Some((ReduceOp::Product, children)),
+            Node::Max { children } => Some((ReduceOp::Max, children)),
+            Node::Reduce { op, children } => Some((*op, children)),
+            _ => None,
+        }
+    }
+}
+
+/// The terms of the product rule for a product of `children`, one per child whose derivative
+/// isn't `None`, meaning zero. Their sum is the derivative of the product.
+///
+/// Writing out every term in full would take O(n^2) children, so instead this shares running
+/// products from either end: term i is prefix[i] * d(child i) * suffix[i]. Only the running
+/// products that some term needs are pushed.
+pub(crate) fn product_rule_terms(
+    children: &[Idx],
+    derivatives: &[Option<Idx>],
+    graph: &mut Graph,
+) -> Vec<Idx> {
+    let n = children.len();
+    let nonzero: Vec<usize> = (0..n).filter(|&i| derivatives[i].is_some()).collect();
+    let (first, last) = match (nonzero.first(), nonzero.last()) {
+        (Some(&first), Some(&last)) => (first, last),
+        _ => return vec![],
+    };
+
+    let mut prefixes: Vec<Option<Idx>> = vec![None; n];
+    for i in 1..=last {
+        prefixes[i] = Some(match prefixes[i - 1] {
+            Some(prefix) => graph.push(prefix * children[i - 1]),
+            None => children[i - 1],
+        });
+    }
+    let mut suffixes: Vec<Option<Idx>> = vec![None; n];
+    for i in (first..n - 1).rev() {
+        suffixes[i] = Some(match suffixes[i + 1] {
+            Some(suffix) => graph.push(children[i + 1] * suffix),
+            None => children[i + 1],
+        });
+    }
+
+    nonzero
+        .into_iter()
+        .map(|i| {
+            let factors: Vec<Idx> = prefixes[i]
+                .into_iter()
+                .chain(derivatives[i])
+                .chain(suffixes[i])
+                .collect();
+            graph.push(Node::Product { children: factors })
+        })
+        .collect()
+}
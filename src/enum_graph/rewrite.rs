@@ -0,0 +1,134 @@
+use super::{Graph, Idx, Node, ReduceOp};
+
+/// A local rewrite: given the graph built so far and a node whose children are already in it,
+/// returns an equivalent node to use instead, or `None` if the rule doesn't match.
+#[derive(Clone, Copy)]
+pub struct Rule {
+    pub name: &'static str,
+    rewrite: fn(&Graph, &Node) -> Option<Node>,
+}
+
+fn constant(graph: &Graph, idx: Idx) -> Option<f64> {
+    match graph[idx] {
+        Node::Constant(value) => Some(value),
+        _ => None,
+    }
+}
+
+impl Rule {
+    pub fn new(name: &'static str, rewrite: fn(&Graph, &Node) -> Option<Node>) -> Self {
+        Self { name, rewrite }
+    }
+
+    /// `x + x + ... + x` becomes `n * x`
+    pub fn collect_terms() -> Self {
+        Self::new("collect_terms", |_, node| match node.as_reduce() {
+            Some((ReduceOp::Sum, children)) if children.len() > 1 => {
+                if children.iter().all(|child| *child == children[0]) {
+                    Some(children[0] * children.len() as f64)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        })
+    }
+
+    /// `x + 0` becomes `x`, written as a sum with one child so that indices don't change
+    pub fn drop_zero_terms() -> Self {
+        Self::new("drop_zero_terms", |graph, node| match node.as_reduce() {
+            Some((ReduceOp::Sum, children)) => {
+                let kept: Vec<Idx> = children
+                    .iter()
+                    .cloned()
+                    .filter(|&child| constant(graph, child) != Some(0.0))
+                    .collect();
+                if kept.len() < children.len() && !kept.is_empty() {
+                    Some(ReduceOp::Sum.node(kept))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        })
+    }
+
+    /// `x * 1` becomes `x`, written as a product with one child
+    pub fn drop_one_factors() -> Self {
+        Self::new("drop_one_factors", |graph, node| match node.as_reduce() {
+            Some((ReduceOp::Product, children)) => {
+                let kept: Vec<Idx> = children
+                    .iter()
+                    .cloned()
+                    .filter(|&child| constant(graph, child) != Some(1.0))
+                    .collect();
+                if kept.len() < children.len() && !kept.is_empty() {
+                    Some(ReduceOp::Product.node(kept))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        })
+    }
+
+    /// `x * 0` becomes `0`. This ignores the possibility that `x` is infinite or NaN.
+    pub fn zero_product() -> Self {
+        Self::new("zero_product", |graph, node| match node.as_reduce() {
+            Some((ReduceOp::Product, children))
+                if children
+                    .iter()
+                    .any(|&child| constant(graph, child) == Some(0.0)) =>
+            {
+                Some(Node::Constant(0.0))
+            }
+            _ => None,
+        })
+    }
+
+    /// All of the built-in rules.
+    pub fn algebraic() -> Vec<Rule> {
+        vec![
+            Rule::collect_terms(),
+            Rule::drop_zero_terms(),
+            Rule::drop_one_factors(),
+            Rule::zero_product(),
+        ]
+    }
+}
+
+impl Graph {
+    /// Rewrites every node with `rules`, trying each rule in turn until none of them match, so
+    /// the rules must not undo each other. Each node is replaced by exactly one node, so every
+    /// `Idx` still refers to an equivalent node in the new graph. Nodes left unused by a rewrite
+    /// stay in the graph.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node, Rule};
+    ///
+    /// let mut g = Graph::default();
+    /// let x = g.push(Node::Variable);
+    /// let sum = g.push(x + x);
+    ///
+    /// let rewritten = g.apply_rules(&Rule::algebraic());
+    /// assert_eq!(x * 2.0, rewritten[sum]);
+    ///
+    /// let values: HashMap<_, _> = vec![(x, 1.5)].into_iter().collect();
+    /// assert_eq!(g.evaluate(values.clone())[&sum], rewritten.evaluate(values)[&sum]);
+    /// ```
+    pub fn apply_rules(&self, rules: &[Rule]) -> Graph {
+        let mut result = Graph::default();
+
+        for node in &self.nodes {
+            let mut node = node.clone();
+            while let Some(rewritten) = rules.iter().find_map(|rule| (rule.rewrite)(&result, &node))
+            {
+                node = rewritten;
+            }
+            result.push(node);
+        }
+
+        result
+    }
+}
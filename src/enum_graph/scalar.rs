@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+
+use super::{Graph, GraphError, Idx, Node, ReduceOp};
+
+/// A number type other than `f64` that a graph can be evaluated with, using
+/// `Graph::evaluate_scalar`. Only constants, variables, sums, products, and scales can be
+/// evaluated this way, since those only need addition and multiplication.
+///
+/// The arithmetic is checked: returning `None` from `checked_add` or `checked_mul` makes the
+/// evaluation fail with `GraphError::Overflow` instead of wrapping.
+///
+/// ```
+/// use std::collections::HashMap;
+/// use exploring_computation_graphs_in_rust::enum_graph::{Graph, GraphError, Idx, Node, Scalar};
+///
+/// // Integers mod 7, which can never overflow
+/// #[derive(Clone, Copy, Debug, PartialEq)]
+/// struct Mod7(u8);
+///
+/// impl Scalar for Mod7 {
+///     fn zero() -> Self {
+///         Mod7(0)
+///     }
+///     fn one() -> Self {
+///         Mod7(1)
+///     }
+///     fn checked_add(self, rhs: Self) -> Option<Self> {
+///         Some(Mod7((self.0 + rhs.0) % 7))
+///     }
+///     fn checked_mul(self, rhs: Self) -> Option<Self> {
+///         Some(Mod7((self.0 * rhs.0) % 7))
+///     }
+///     fn from_f64(value: f64) -> Option<Self> {
+///         if value.fract() == 0.0 && value >= 0.0 && value < 7.0 {
+///             Some(Mod7(value as u8))
+///         } else {
+///             None
+///         }
+///     }
+///     fn unsupported(idx: Idx) -> GraphError {
+///         GraphError::Unconvertible(idx)
+///     }
+/// }
+///
+/// let mut g = Graph::default();
+/// let a = g.push(Node::Variable);
+/// let b = g.push(Node::Constant(4.0));
+/// let product = g.push(a * b);
+/// let half = g.push(Node::Constant(0.5));
+///
+/// let values: HashMap<_, _> = vec![(a, Mod7(5))].into_iter().collect();
+/// assert_eq!(Err(GraphError::Unconvertible(half)), g.evaluate_scalar(values.clone()));
+/// g.truncate(3).unwrap();
+/// assert_eq!(Mod7(6), g.evaluate_scalar(values).unwrap()[&product]);
+/// ```
+pub trait Scalar: Copy {
+    fn zero() -> Self;
+
+    fn one() -> Self;
+
+    /// The sum, or `None` if it doesn't fit.
+    fn checked_add(self, rhs: Self) -> Option<Self>;
+
+    /// The product, or `None` if it doesn't fit.
+    fn checked_mul(self, rhs: Self) -> Option<Self>;
+
+    /// The value of a constant or a scale factor, or `None` if this type can't represent it.
+    fn from_f64(value: f64) -> Option<Self>;
+
+    /// The error for a node that can't be evaluated with this type, either because of its kind
+    /// or because `from_f64` couldn't represent its value.
+    fn unsupported(idx: Idx) -> GraphError;
+}
+
+impl Graph {
+    /// Evaluates the graph with a `Scalar` for each variable, instead of an `f64`. Nodes other
+    /// than constants, variables, sums, products, and scales return `Scalar::unsupported`, and
+    /// so do constants and scale factors that `Scalar::from_f64` rejects. If a value doesn't
+    /// fit, this returns `GraphError::Overflow`.
+    pub fn evaluate_scalar<S: Scalar>(
+        &self,
+        values: HashMap<Idx, S>,
+    ) -> Result<HashMap<Idx, S>, GraphError> {
+        let mut result = values;
+
+        for (i, node) in self.nodes.iter().enumerate() {
+            let index = Idx(i);
+            let mut children = node.children().into_iter().map(|child| result[&child]);
+            let value = match node {
+                Node::Constant(value) => {
+                    Some(S::from_f64(*value).ok_or_else(|| S::unsupported(index))?)
+                }
+                Node::Variable => Some(result[&index]),
+                Node::Sum { .. }
+                | Node::Reduce {
+                    op: ReduceOp::Sum, ..
+                } => children.try_fold(S::zero(), S::checked_add),
+                Node::Product { .. }
+                | Node::Reduce {
+                    op: ReduceOp::Product,
+                    ..
+                } => children.try_fold(S::one(), S::checked_mul),
+                Node::Scale { child, factor } => {
+                    let factor = S::from_f64(*factor).ok_or_else(|| S::unsupported(index))?;
+                    result[child].checked_mul(factor)
+                }
+                _ => return Err(S::unsupported(index)),
+            };
+            result.insert(index, value.ok_or(GraphError::Overflow(index))?);
+        }
+
+        Ok(result)
+    }
+}
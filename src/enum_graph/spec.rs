@@ -0,0 +1,86 @@
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+
+use super::{Graph, Node};
+
+/// A plain description of one node, for assembling graphs from data. The kind and parameters
+/// are the same as in `Graph::to_edge_list`, and children are the positions of earlier specs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NodeSpec {
+    pub kind: String,
+    pub parameters: Vec<f64>,
+    pub children: Vec<usize>,
+}
+
+impl NodeSpec {
+    pub fn new(kind: &str, parameters: Vec<f64>, children: Vec<usize>) -> Self {
+        Self {
+            kind: kind.to_string(),
+            parameters,
+            children,
+        }
+    }
+}
+
+/// Describes why a list of `NodeSpec`s couldn't be turned into a graph. `index` is the position
+/// of the spec that was rejected.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpecError {
+    pub index: usize,
+    pub message: String,
+}
+
+impl fmt::Display for SpecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "node {}: {}", self.index, self.message)
+    }
+}
+
+impl Error for SpecError {}
+
+/// Builds a graph with one node per spec, in order. Every child must refer to an earlier spec,
+/// which also rules out cycles.
+///
+/// ```
+/// use std::convert::TryFrom;
+/// use exploring_computation_graphs_in_rust::enum_graph::{Graph, NodeSpec};
+///
+/// let specs = vec![
+///     NodeSpec::new("constant", vec![2.0], vec![]),
+///     NodeSpec::new("variable", vec![], vec![]),
+///     NodeSpec::new("product", vec![], vec![0, 1]),
+/// ];
+/// let g = Graph::try_from(specs).unwrap();
+/// assert_eq!("0,constant,2\n1,variable\n2,product,0,1\n", g.to_edge_list());
+///
+/// // A child can't come after its parent
+/// let specs = vec![
+///     NodeSpec::new("exp", vec![], vec![1]),
+///     NodeSpec::new("variable", vec![], vec![]),
+/// ];
+/// assert_eq!(0, Graph::try_from(specs).unwrap_err().index);
+/// ```
+impl TryFrom<Vec<NodeSpec>> for Graph {
+    type Error = SpecError;
+
+    fn try_from(specs: Vec<NodeSpec>) -> Result<Graph, SpecError> {
+        let mut graph = Graph::default();
+
+        for (index, spec) in specs.iter().enumerate() {
+            // Reuse the edge list's parser, which already validates every kind of node
+            let fields: Vec<String> = spec
+                .parameters
+                .iter()
+                .map(f64::to_string)
+                .chain(spec.children.iter().map(usize::to_string))
+                .collect();
+            let fields: Vec<&str> = fields.iter().map(String::as_str).collect();
+            let node = Node::from_fields(&spec.kind, &fields, index)
+                .map_err(|message| SpecError { index, message })?;
+            graph.push(node);
+        }
+
+        Ok(graph)
+    }
+}
@@ -0,0 +1,63 @@
+//! Helpers for checking the values that a graph computes, for use in tests.
+
+use std::collections::HashMap;
+
+use super::{Bindings, Graph, Idx};
+
+/// Evaluates `g` and checks that each node in `expected` is within `tol` of its expected value.
+/// All of the mismatches are reported at once.
+///
+/// # Panics
+///
+/// Panics with one line per mismatch if any node is off by more than `tol`.
+///
+/// ```
+/// use std::collections::HashMap;
+/// use std::panic::{self, AssertUnwindSafe};
+/// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+/// use exploring_computation_graphs_in_rust::enum_graph::testing::assert_graph_eval_close;
+///
+/// let mut g = Graph::default();
+/// let a = g.push(Node::Variable);
+/// let b = g.push(Node::Exp { child: a });
+/// let c = g.push(a * b);
+/// let values: HashMap<_, _> = vec![(a, 1.0)].into_iter().collect();
+///
+/// assert_graph_eval_close(&g, values.clone(), &[(b, 2.718), (c, 2.718)], 1e-3);
+///
+/// let error = panic::catch_unwind(AssertUnwindSafe(|| {
+///     assert_graph_eval_close(&g, values, &[(a, 1.0), (b, 3.0), (c, 0.0)], 1e-3)
+/// }))
+/// .unwrap_err();
+/// let message = error.downcast_ref::<String>().unwrap();
+/// assert!(message.contains("node 1: expected 3, got 2.718"));
+/// assert!(message.contains("node 2: expected 0, got 2.718"));
+/// assert!(!message.contains("node 0"));
+/// ```
+pub fn assert_graph_eval_close(
+    g: &Graph,
+    values: impl Into<Bindings>,
+    expected: &[(Idx, f64)],
+    tol: f64,
+) {
+    let result: HashMap<Idx, f64> = g.evaluate(values);
+    let mismatches: Vec<String> = expected
+        .iter()
+        .filter(|&&(idx, value)| {
+            // NaN is never close to anything
+            let error = (result[&idx] - value).abs();
+            error.is_nan() || error > tol
+        })
+        .map(|&(idx, value)| format!("node {}: expected {}, got {}", idx.0, value, result[&idx]))
+        .collect();
+
+    if !mismatches.is_empty() {
+        panic!(
+            "{} of {} nodes were not within {}:\n{}",
+            mismatches.len(),
+            expected.len(),
+            tol,
+            mismatches.join("\n")
+        );
+    }
+}
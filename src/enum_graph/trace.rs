@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use super::{Graph, Idx};
+
+/// One step of an evaluation: which node was computed, from what, and what it came to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceEntry {
+    pub index: Idx,
+    pub kind: &'static str,
+    /// The values of the children, in order
+    pub inputs: Vec<f64>,
+    pub output: f64,
+}
+
+/// Prints the entry as e.g. `2: sum(1, 2) = 3`.
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let inputs: Vec<String> = self.inputs.iter().map(f64::to_string).collect();
+        write!(
+            f,
+            "{}: {}({}) = {}",
+            self.index.0,
+            self.kind,
+            inputs.join(", "),
+            self.output
+        )
+    }
+}
+
+impl Graph {
+    /// Like `evaluate`, but also records one `TraceEntry` per node, in the order they were
+    /// evaluated.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Constant(1.0));
+    /// let b = g.push(Node::Variable);
+    /// let c = g.push(a + b);
+    ///
+    /// let values: HashMap<_, _> = vec![(b, 2.0)].into_iter().collect();
+    /// let (result, trace) = g.evaluate_traced(values);
+    /// assert_eq!(3.0, result[&c]);
+    ///
+    /// let indices: Vec<_> = trace.iter().map(|entry| entry.index).collect();
+    /// assert_eq!(vec![a, b, c], indices);
+    /// assert_eq!(vec![1.0, 2.0], trace[2].inputs);
+    /// assert_eq!("2: sum(1, 2) = 3", trace[2].to_string());
+    /// ```
+    pub fn evaluate_traced(
+        &self,
+        values: HashMap<Idx, f64>,
+    ) -> (HashMap<Idx, f64>, Vec<TraceEntry>) {
+        let mut result = values;
+        let mut trace = Vec::with_capacity(self.len());
+
+        for (i, node) in self.nodes.iter().enumerate() {
+            let index = Idx(i);
+            let output = node.get_value(&index, &result);
+            trace.push(TraceEntry {
+                index,
+                kind: node.kind(),
+                inputs: node.children().iter().map(|child| result[child]).collect(),
+                output,
+            });
+            result.insert(index, output);
+        }
+
+        (result, trace)
+    }
+}
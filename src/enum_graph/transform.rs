@@ -0,0 +1,202 @@
+use std::collections::{HashMap, HashSet};
+
+use super::{Graph, Idx, Node};
+
+/// While folding, a node has either been reduced to a known value or it still needs to be
+/// computed by a node in the new graph.
+#[derive(Clone, Copy)]
+enum Folded {
+    Value(f64),
+    Node(Idx),
+}
+
+impl Graph {
+    /// Substitutes the `fixed` variables with constants and folds every node whose value no
+    /// longer depends on a free variable. Returns the residual graph, along with a map from
+    /// each original node that's still present (including all free variables and outputs) to
+    /// its index in the residual graph.
+    ///
+    /// ```
+    /// use std::collections::{HashMap, HashSet};
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// // (a * 3) + exp(b)
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Variable);
+    /// let three = g.push(Node::Constant(3.0));
+    /// let product = g.push(a * three);
+    /// let exp_b = g.push(Node::Exp { child: b });
+    /// let output = g.push(product + exp_b);
+    ///
+    /// let fixed: HashMap<_, _> = vec![(b, 0.0)].into_iter().collect();
+    /// let (residual, remap) = g.partial_evaluate(fixed);
+    /// assert!(residual.len() < g.len());
+    /// assert!(!remap.contains_key(&b));
+    ///
+    /// let values: HashMap<_, _> = vec![(remap[&a], 2.0)].into_iter().collect();
+    /// assert_eq!(7.0, residual.evaluate(values)[&remap[&output]]);
+    /// ```
+    pub fn partial_evaluate(&self, fixed: HashMap<Idx, f64>) -> (Graph, HashMap<Idx, Idx>) {
+        let mut residual = Graph::default();
+        let mut folded: Vec<Folded> = Vec::with_capacity(self.len());
+        let mut remap = HashMap::new();
+        // Each folded value only needs to become a constant once
+        let mut constants: HashMap<Idx, Idx> = HashMap::new();
+
+        for (i, node) in self.nodes.iter().enumerate() {
+            let old_index = Idx(i);
+            let children = node.children();
+
+            let state = if let Some(&value) = fixed.get(&old_index) {
+                Folded::Value(value)
+            } else if !matches!(
+                node,
+                Node::Variable | Node::VectorConstant(_) | Node::External { .. }
+            ) && children
+                .iter()
+                .all(|child| matches!(folded[child.0], Folded::Value(_)))
+            {
+                let child_values: HashMap<Idx, f64> = children
+                    .iter()
+                    .map(|child| match folded[child.0] {
+                        Folded::Value(value) => (*child, value),
+                        Folded::Node(_) => unreachable!(),
+                    })
+                    .collect();
+                Folded::Value(node.get_value(&old_index, &child_values))
+            } else {
+                let new_node = node.map_children(|child| match folded[child.0] {
+                    Folded::Node(new_child) => new_child,
+                    Folded::Value(value) => *constants
+                        .entry(child)
+                        .or_insert_with(|| residual.push(Node::Constant(value))),
+                });
+                let new_index = residual.push(new_node);
+                remap.insert(old_index, new_index);
+                Folded::Node(new_index)
+            };
+            folded.push(state);
+        }
+
+        // Outputs that were folded away still need to be available
+        let used: HashSet<Idx> = self.nodes.iter().flat_map(|node| node.children()).collect();
+        for (i, state) in folded.iter().enumerate() {
+            if let Folded::Value(value) = *state {
+                if !used.contains(&Idx(i)) && !fixed.contains_key(&Idx(i)) {
+                    remap.insert(Idx(i), residual.push(Node::Constant(value)));
+                }
+            }
+        }
+
+        (residual, remap)
+    }
+
+    /// The number of operations on the longest path that `partial_evaluate` would fold away
+    /// even with no variables fixed, i.e. the depth of the deepest subtree that only depends on
+    /// constants. Constants themselves have a depth of zero.
+    ///
+    /// ```
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let mut chain = g.push(Node::Constant(0.5));
+    /// for _ in 0..5 {
+    ///     chain = g.push(Node::Tanh { child: chain });
+    /// }
+    /// let x = g.push(Node::Variable);
+    /// g.push(chain + x);
+    ///
+    /// assert_eq!(5, g.max_constant_chain());
+    /// ```
+    pub fn max_constant_chain(&self) -> usize {
+        // The depth of each node, or None if it depends on something that isn't a constant
+        let mut depths: Vec<Option<usize>> = Vec::with_capacity(self.len());
+
+        for node in &self.nodes {
+            let depth = match node {
+                Node::Variable | Node::VectorConstant(_) | Node::External { .. } => None,
+                Node::Constant(_) => Some(0),
+                _ => node
+                    .children()
+                    .iter()
+                    .map(|child| depths[child.0])
+                    .collect::<Option<Vec<usize>>>()
+                    .map(|child_depths| 1 + child_depths.into_iter().max().unwrap_or(0)),
+            };
+            depths.push(depth);
+        }
+
+        depths.into_iter().flatten().max().unwrap_or(0)
+    }
+
+    /// Collapses chains of `Scale` nodes into a single `Scale` whose factor is the product of
+    /// the chain's factors. Returns the new graph and a map from each original node that's
+    /// still present to its new index. A scale that only feeds into other scales is left out.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let x = g.push(Node::Variable);
+    /// let inner = g.push(x * 2.0);
+    /// let outer = g.push(inner * 3.0);
+    ///
+    /// let (fused, remap) = g.fuse_scales();
+    /// assert_eq!(2, fused.len());
+    /// assert_eq!(remap[&x] * 6.0, fused[remap[&outer]]);
+    /// assert!(!remap.contains_key(&inner));
+    ///
+    /// let values: HashMap<_, _> = vec![(x, 1.5)].into_iter().collect();
+    /// let fused_values: HashMap<_, _> = vec![(remap[&x], 1.5)].into_iter().collect();
+    /// assert_eq!(g.evaluate(values)[&outer], fused.evaluate(fused_values)[&remap[&outer]]);
+    /// ```
+    pub fn fuse_scales(&self) -> (Graph, HashMap<Idx, Idx>) {
+        let mut fused = Graph::default();
+        let mut remap: HashMap<Idx, Idx> = HashMap::new();
+        // Every scale, as the node at the bottom of its chain and the total factor. A scale is
+        // only pushed once something other than a scale needs it.
+        let mut chains: HashMap<Idx, (Idx, f64)> = HashMap::new();
+
+        fn materialize(
+            old_index: Idx,
+            chains: &HashMap<Idx, (Idx, f64)>,
+            remap: &mut HashMap<Idx, Idx>,
+            fused: &mut Graph,
+        ) -> Idx {
+            if let Some(&new_index) = remap.get(&old_index) {
+                return new_index;
+            }
+            let (base, factor) = chains[&old_index];
+            let new_index = fused.push(remap[&base] * factor);
+            remap.insert(old_index, new_index);
+            new_index
+        }
+
+        for (i, node) in self.nodes.iter().enumerate() {
+            let old_index = Idx(i);
+            if let Node::Scale { child, factor } = *node {
+                let chain = match chains.get(&child) {
+                    Some(&(base, child_factor)) => (base, child_factor * factor),
+                    None => (child, factor),
+                };
+                chains.insert(old_index, chain);
+            } else {
+                let new_node =
+                    node.map_children(|child| materialize(child, &chains, &mut remap, &mut fused));
+                remap.insert(old_index, fused.push(new_node));
+            }
+        }
+
+        // Scales that are outputs still need to be available
+        let used: HashSet<Idx> = self.nodes.iter().flat_map(|node| node.children()).collect();
+        for i in 0..self.len() {
+            if chains.contains_key(&Idx(i)) && !used.contains(&Idx(i)) {
+                materialize(Idx(i), &chains, &mut remap, &mut fused);
+            }
+        }
+
+        (fused, remap)
+    }
+}
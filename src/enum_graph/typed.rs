@@ -0,0 +1,81 @@
+use std::collections::HashSet;
+use std::marker::PhantomData;
+
+use super::{Graph, GraphError, Idx, Node, Subgraph};
+
+/// Marks a `TypedIdx` that points at a `Node::Variable`.
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub struct VariableKind;
+
+/// Marks a `TypedIdx` that points at a `Node::Constant`.
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub struct ConstantKind;
+
+/// An `Idx` that also knows what kind of node it points at, so that operations which only make
+/// sense for one kind of node can say so in their signature.
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub struct TypedIdx<K> {
+    idx: Idx,
+    kind: PhantomData<K>,
+}
+
+impl<K> TypedIdx<K> {
+    fn new(idx: Idx) -> Self {
+        Self {
+            idx,
+            kind: PhantomData,
+        }
+    }
+
+    /// Forgets the kind, giving back the plain index.
+    pub fn erase(self) -> Idx {
+        self.idx
+    }
+}
+
+impl Graph {
+    pub fn push_variable(&mut self) -> TypedIdx<VariableKind> {
+        TypedIdx::new(self.push(Node::Variable))
+    }
+
+    pub fn push_constant(&mut self, value: f64) -> TypedIdx<ConstantKind> {
+        TypedIdx::new(self.push(Node::Constant(value)))
+    }
+
+    /// The same as `derivative`, except that the type system guarantees that we're only
+    /// differentiating with respect to variables.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::Graph;
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push_constant(1.0);
+    /// let b = g.push_variable();
+    /// let c = g.push(a.erase() + b.erase());
+    ///
+    /// let (d_c_b, subgraph) = g.derivative_typed(c, &[b]).unwrap();
+    /// assert_eq!(1.0, g.evaluate_subgraph(subgraph, HashMap::new())[&d_c_b]);
+    /// ```
+    ///
+    /// Passing a constant doesn't compile:
+    ///
+    /// ```compile_fail
+    /// use exploring_computation_graphs_in_rust::enum_graph::Graph;
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push_constant(1.0);
+    /// let b = g.push_variable();
+    /// let c = g.push(a.erase() + b.erase());
+    ///
+    /// g.derivative_typed(c, &[a]).unwrap();
+    /// ```
+    pub fn derivative_typed(
+        &mut self,
+        of: Idx,
+        wrt: &[TypedIdx<VariableKind>],
+    ) -> Result<(Idx, Subgraph), GraphError> {
+        let wrt: HashSet<Idx> = wrt.iter().map(|idx| idx.erase()).collect();
+        self.derivative(of, wrt)
+    }
+}
@@ -0,0 +1,395 @@
+use std::collections::HashMap;
+use std::iter;
+
+use super::{Graph, GraphError, Idx, Node};
+
+/// The product of a `rows` by `inner` matrix and an `inner` by `columns` matrix, all in
+/// row-major order.
+fn multiply(a: &[f64], b: &[f64], rows: usize, inner: usize, columns: usize) -> Vec<f64> {
+    let mut output = vec![0.0; rows * columns];
+    for row in 0..rows {
+        for k in 0..inner {
+            for column in 0..columns {
+                output[row * columns + column] += a[row * inner + k] * b[k * columns + column];
+            }
+        }
+    }
+    output
+}
+
+/// Swaps the rows and columns of a `rows` by `columns` matrix.
+fn transpose(matrix: &[f64], rows: usize, columns: usize) -> Vec<f64> {
+    (0..columns * rows)
+        .map(|i| matrix[(i % rows) * columns + i / rows])
+        .collect()
+}
+
+/// Applies `f` to each lane.
+fn map_lanes(lanes: &[f64; 8], f: impl Fn(f64) -> f64) -> [f64; 8] {
+    let mut output = [0.0; 8];
+    for (output, &lane) in output.iter_mut().zip(lanes) {
+        *output = f(lane);
+    }
+    output
+}
+
+impl Graph {
+    /// Evaluates the graph with a vector of values at every node. Scalar constants are vectors
+    /// of length one. `Normalize`, `Reshape`, and `MatMul` work on the whole vector, and
+    /// everything else works element by element, so all of a node's children must have the
+    /// same length, which is also the length of its output; otherwise this returns
+    /// `GraphError::LengthMismatch`.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, GraphError, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::VectorConstant(vec![1.0, 2.0, 3.0]));
+    /// let b = g.push(Node::Variable);
+    /// let sum = g.push(a + b);
+    /// let product = g.push(a * b);
+    ///
+    /// let values: HashMap<_, _> = vec![(b, vec![10.0, 20.0, 30.0])].into_iter().collect();
+    /// let result = g.evaluate_vectors(values).unwrap();
+    /// assert_eq!(vec![11.0, 22.0, 33.0], result[&sum]);
+    /// assert_eq!(vec![10.0, 40.0, 90.0], result[&product]);
+    ///
+    /// let values: HashMap<_, _> = vec![(b, vec![10.0, 20.0])].into_iter().collect();
+    /// assert_eq!(Err(GraphError::LengthMismatch(sum)), g.evaluate_vectors(values));
+    /// ```
+    pub fn evaluate_vectors(
+        &self,
+        values: HashMap<Idx, Vec<f64>>,
+    ) -> Result<HashMap<Idx, Vec<f64>>, GraphError> {
+        let mut result = values;
+
+        for (i, node) in self.nodes.iter().enumerate() {
+            let index = Idx(i);
+            let value = match node {
+                Node::Constant(value) => vec![*value],
+                Node::VectorConstant(values) => values.clone(),
+                Node::Variable | Node::External { .. } => result[&index].clone(),
+                Node::Normalize { child } => {
+                    let norm = result[child].iter().map(|x| x * x).sum::<f64>().sqrt();
+                    if norm == 0.0 {
+                        result[child].clone()
+                    } else {
+                        result[child].iter().map(|x| x / norm).collect()
+                    }
+                }
+                Node::Reshape { child, shape } => {
+                    if result[child].len() != shape.iter().product::<usize>() {
+                        return Err(GraphError::LengthMismatch(index));
+                    }
+                    result[child].clone()
+                }
+                Node::MatMul { a, b } => {
+                    let (rows, columns) = self
+                        .matrix_shape(index)
+                        .ok_or(GraphError::LengthMismatch(index))?;
+                    let inner = result[a].len() / rows.max(1);
+                    multiply(&result[a], &result[b], rows, inner, columns)
+                }
+                _ => {
+                    let children = node.children();
+                    let len = children.first().map_or(1, |child| result[child].len());
+                    if children.iter().any(|child| result[child].len() != len) {
+                        return Err(GraphError::LengthMismatch(index));
+                    }
+
+                    // Evaluate the scalar version of the node once per element
+                    (0..len)
+                        .map(|element| {
+                            let element_values: HashMap<Idx, f64> = children
+                                .iter()
+                                .map(|child| (*child, result[child][element]))
+                                .collect();
+                            node.get_value(&index, &element_values)
+                        })
+                        .collect()
+                }
+            };
+            result.insert(index, value);
+        }
+
+        Ok(result)
+    }
+
+    /// The rows and columns of `idx`, if it's a matrix: a two-dimensional `Reshape`, or a
+    /// `MatMul` whose children are matrices with shapes that line up.
+    pub fn matrix_shape(&self, idx: Idx) -> Option<(usize, usize)> {
+        match &self[idx] {
+            Node::Reshape { shape, .. } if shape.len() == 2 => Some((shape[0], shape[1])),
+            Node::MatMul { a, b } => {
+                let (rows, inner) = self.matrix_shape(*a)?;
+                let (b_rows, columns) = self.matrix_shape(*b)?;
+                if inner == b_rows {
+                    Some((rows, columns))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Reverse mode on the values from `evaluate_vectors`: the gradient of the sum of the
+    /// elements of `of` with respect to every node that it depends on, with one element per
+    /// element of the node. `MatMul` and `Normalize` use the gradients of the whole vector, and
+    /// everything else uses its scalar gradients element by element. This returns the same
+    /// errors as `evaluate_vectors`, or `GraphError::NotTwiceDifferentiable` for a custom node
+    /// without a gradient.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let x = g.push(Node::Variable);
+    /// let unit = g.push(Node::Normalize { child: x });
+    /// let scaled = g.push(unit * 2.0);
+    ///
+    /// let values: HashMap<_, _> = vec![(x, vec![3.0, 4.0])].into_iter().collect();
+    /// let gradients = g.vector_gradients(scaled, values).unwrap();
+    /// assert_eq!(vec![2.0, 2.0], gradients[&unit]);
+    /// // 2 * (1 - u * (u · 1)) / |x|, where u = (0.6, 0.8)
+    /// assert!((gradients[&x][0] - 0.064).abs() < 1e-12);
+    /// assert!((gradients[&x][1] + 0.048).abs() < 1e-12);
+    /// ```
+    pub fn vector_gradients(
+        &self,
+        of: Idx,
+        values: HashMap<Idx, Vec<f64>>,
+    ) -> Result<HashMap<Idx, Vec<f64>>, GraphError> {
+        let subgraph = self.subgraph_for(&[of]);
+        self.check_differentiable(&subgraph)?;
+        let values = self.evaluate_vectors(values)?;
+
+        let mut gradients = HashMap::new();
+        gradients.insert(of, vec![1.0; values[&of].len()]);
+
+        for &index in subgraph.indices().iter().rev() {
+            let adjoint = match gradients.get(&index) {
+                Some(adjoint) => adjoint.clone(),
+                None => continue,
+            };
+            let node = &self[index];
+            let child_gradients: Vec<(Idx, Vec<f64>)> = match node {
+                Node::MatMul { a, b } => {
+                    // evaluate_vectors already checked the shapes
+                    let (rows, columns) = self.matrix_shape(index).unwrap();
+                    let inner = values[a].len() / rows.max(1);
+                    let b_transposed = transpose(&values[b], inner, columns);
+                    let a_transposed = transpose(&values[a], rows, inner);
+                    vec![
+                        (*a, multiply(&adjoint, &b_transposed, rows, columns, inner)),
+                        (*b, multiply(&a_transposed, &adjoint, inner, rows, columns)),
+                    ]
+                }
+                Node::Normalize { child } => {
+                    // The part of the adjoint along the unit vector doesn't change the output
+                    let norm = values[child].iter().map(|x| x * x).sum::<f64>().sqrt();
+                    if norm == 0.0 {
+                        vec![(*child, adjoint)]
+                    } else {
+                        let unit = &values[&index];
+                        let along: f64 = unit.iter().zip(&adjoint).map(|(u, g)| u * g).sum();
+                        let gradient = unit
+                            .iter()
+                            .zip(&adjoint)
+                            .map(|(u, g)| (g - u * along) / norm)
+                            .collect();
+                        vec![(*child, gradient)]
+                    }
+                }
+                _ => {
+                    let children = node.children();
+                    let mut gradients = vec![vec![0.0; adjoint.len()]; children.len()];
+                    for (element, adjoint) in adjoint.iter().enumerate() {
+                        let mut element_values: HashMap<Idx, f64> = children
+                            .iter()
+                            .map(|child| (*child, values[child][element]))
+                            .collect();
+                        element_values.insert(index, values[&index][element]);
+                        for (gradient, partial) in gradients
+                            .iter_mut()
+                            .zip(node.local_gradients(&index, &element_values))
+                        {
+                            gradient[element] = adjoint * partial;
+                        }
+                    }
+                    children.into_iter().zip(gradients).collect()
+                }
+            };
+
+            for (child, gradient) in child_gradients {
+                let total = gradients
+                    .entry(child)
+                    .or_insert_with(|| vec![0.0; gradient.len()]);
+                for (total, element) in total.iter_mut().zip(gradient) {
+                    *total += element;
+                }
+            }
+        }
+
+        Ok(gradients)
+    }
+
+    /// Evaluates the graph for eight sets of variable values at once, one per lane. `batch`
+    /// holds the lanes for each variable, in index order, and the result holds the lanes for
+    /// each node, also in index order.
+    ///
+    /// The lanes are kept together in fixed-size arrays. Constants, variables, reductions (both
+    /// the dedicated variants and `Reduce`), scales, powers, and the common unary functions are
+    /// computed with a plain loop over the lanes, which the compiler can vectorize. This is portable code with no explicit SIMD
+    /// instructions. Any other node falls back to evaluating each lane with `get_value`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `batch` doesn't have an entry for every variable.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Variable);
+    /// let product = g.push(a * b);
+    /// let exp = g.push(Node::Exp { child: product });
+    /// let scaled = g.push(exp * 0.5);
+    /// let sum = g.push(scaled + a);
+    /// // This one falls back to evaluating each lane
+    /// let gelu = g.push(Node::Gelu { child: sum });
+    ///
+    /// let a_lanes = [0.0, 0.5, 1.0, 1.5, 2.0, 2.5, 3.0, 3.5];
+    /// let b_lanes = [1.0, -1.0, 2.0, -2.0, 0.5, -0.5, 0.25, 0.0];
+    /// let lanes = g.evaluate_simd(&[a_lanes, b_lanes]);
+    ///
+    /// for lane in 0..8 {
+    ///     let values: HashMap<_, _> =
+    ///         vec![(a, a_lanes[lane]), (b, b_lanes[lane])].into_iter().collect();
+    ///     let scalar = g.evaluate(values);
+    ///     for &(idx, position) in &[(product, 2), (exp, 3), (scaled, 4), (sum, 5), (gelu, 6)] {
+    ///         assert_eq!(scalar[&idx], lanes[position][lane]);
+    ///     }
+    /// }
+    /// ```
+    pub fn evaluate_simd(&self, batch: &[[f64; 8]]) -> Vec<[f64; 8]> {
+        let mut variables = batch.iter();
+        let mut result: Vec<[f64; 8]> = Vec::with_capacity(self.len());
+
+        for (i, node) in self.nodes.iter().enumerate() {
+            let index = Idx(i);
+            let lanes = match node {
+                Node::Variable => *variables
+                    .next()
+                    .expect("every variable needs a batch entry"),
+                Node::Constant(value) => [*value; 8],
+                // Sums, products, maxes, and mins, whether dedicated or written as a Reduce
+                _ if node.as_reduce().is_some() => {
+                    let (op, children) = node.as_reduce().unwrap();
+                    let start = [op.apply(iter::empty()); 8];
+                    children.iter().fold(start, |mut total, child| {
+                        for (total, value) in total.iter_mut().zip(&result[child.0]) {
+                            *total = op.apply([*total, *value].iter().cloned());
+                        }
+                        total
+                    })
+                }
+                Node::Scale { child, factor } => map_lanes(&result[child.0], |x| factor * x),
+                Node::Pow { child, exponent } => map_lanes(&result[child.0], |x| x.powf(*exponent)),
+                Node::Exp { child } => map_lanes(&result[child.0], f64::exp),
+                Node::Ln { child } => map_lanes(&result[child.0], f64::ln),
+                Node::Tanh { child } => map_lanes(&result[child.0], f64::tanh),
+                Node::Sinh { child } => map_lanes(&result[child.0], f64::sinh),
+                Node::Cosh { child } => map_lanes(&result[child.0], f64::cosh),
+                Node::Atan { child } => map_lanes(&result[child.0], f64::atan),
+                Node::Asin { child } => map_lanes(&result[child.0], f64::asin),
+                _ => {
+                    let children = node.children();
+                    let mut lanes = [0.0; 8];
+                    for (lane, output) in lanes.iter_mut().enumerate() {
+                        let lane_values: HashMap<Idx, f64> = children
+                            .iter()
+                            .map(|child| (*child, result[child.0][lane]))
+                            .collect();
+                        *output = node.get_value(&index, &lane_values);
+                    }
+                    lanes
+                }
+            };
+            result.push(lanes);
+        }
+
+        result
+    }
+
+    /// Evaluates the graph once per row, where `var_order` says which variable each column of
+    /// the rows is for. Returns the value of every node for each row, in index order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a row doesn't have one value per variable in `var_order`.
+    ///
+    /// ```
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let a = g.push(Node::Variable);
+    /// let b = g.push(Node::Variable);
+    /// let product = g.push(a * b);
+    ///
+    /// let rows = vec![vec![1.0, 2.0], vec![3.0, 4.0], vec![5.0, 6.0]];
+    /// let result = g.evaluate_array(&[a, b], &rows);
+    /// assert_eq!(
+    ///     vec![vec![1.0, 2.0, 2.0], vec![3.0, 4.0, 12.0], vec![5.0, 6.0, 30.0]],
+    ///     result
+    /// );
+    ///
+    /// // The columns can be in any order
+    /// assert_eq!(vec![vec![2.0, 1.0, 2.0]], g.evaluate_array(&[b, a], &rows[..1]));
+    /// ```
+    pub fn evaluate_array(&self, var_order: &[Idx], rows: &[Vec<f64>]) -> Vec<Vec<f64>> {
+        rows.iter()
+            .map(|row| {
+                assert_eq!(
+                    var_order.len(),
+                    row.len(),
+                    "every row needs one value per variable"
+                );
+                let values: HashMap<Idx, f64> =
+                    var_order.iter().cloned().zip(row.iter().cloned()).collect();
+                let result = self.evaluate(values);
+                (0..self.len()).map(|i| result[&Idx(i)]).collect()
+            })
+            .collect()
+    }
+
+    /// Pushes a vector constant of length `len` that is one at `index` and zero everywhere else,
+    /// as used for classification targets.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is not less than `len`.
+    ///
+    /// ```
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut g = Graph::default();
+    /// let target = g.push_one_hot(1, 3);
+    /// assert_eq!(Node::VectorConstant(vec![0.0, 1.0, 0.0]), g[target]);
+    /// ```
+    pub fn push_one_hot(&mut self, index: usize, len: usize) -> Idx {
+        assert!(
+            index < len,
+            "one-hot index {} is out of range for length {}",
+            index,
+            len
+        );
+        let mut values = vec![0.0; len];
+        values[index] = 1.0;
+        self.push(Node::VectorConstant(values))
+    }
+}
@@ -0,0 +1,79 @@
+use std::io::{self, Read, Write};
+
+use super::{Graph, Node};
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+impl Graph {
+    /// Writes the values of every `Constant` and `VectorConstant` node, in index order. Each
+    /// node is written as its length, as a little-endian `u64`, followed by its values as
+    /// little-endian `f64`s. A scalar constant has length one.
+    pub fn save_constants_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        for node in &self.nodes {
+            let values: &[f64] = match node {
+                Node::Constant(value) => std::slice::from_ref(value),
+                Node::VectorConstant(values) => values,
+                _ => continue,
+            };
+            writer.write_all(&(values.len() as u64).to_le_bytes())?;
+            for value in values {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the format written by `save_constants_to`, replacing the values of the constants
+    /// in index order, like loading a model's weights. Each length must match the node it's
+    /// for. If this fails partway through, the constants before the failure have already been
+    /// replaced.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{Graph, Node};
+    ///
+    /// let mut trained = Graph::default();
+    /// let weight = trained.push(Node::Constant(0.5));
+    /// let bias = trained.push(Node::VectorConstant(vec![1.0, -1.0]));
+    /// let x = trained.push(Node::Variable);
+    /// trained.push(weight * x);
+    /// let mut bytes = Vec::new();
+    /// trained.save_constants_to(&mut bytes).unwrap();
+    ///
+    /// let mut g = Graph::default();
+    /// g.push(Node::Constant(0.0));
+    /// g.push(Node::VectorConstant(vec![0.0, 0.0]));
+    /// let x = g.push(Node::Variable);
+    /// g.push(weight * x);
+    /// g.load_constants_from(&bytes[..]).unwrap();
+    /// assert_eq!(trained, g);
+    ///
+    /// // Not enough data
+    /// assert!(g.load_constants_from(&bytes[..12]).is_err());
+    /// ```
+    pub fn load_constants_from<R: Read>(&mut self, mut reader: R) -> io::Result<()> {
+        for node in &mut self.nodes {
+            let values: &mut [f64] = match node {
+                Node::Constant(value) => std::slice::from_mut(value),
+                Node::VectorConstant(values) => values,
+                _ => continue,
+            };
+
+            let len = read_u64(&mut reader)?;
+            if len != values.len() as u64 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("expected {} values, found {}", values.len(), len),
+                ));
+            }
+            for value in values.iter_mut() {
+                *value = f64::from_bits(read_u64(&mut reader)?);
+            }
+        }
+        Ok(())
+    }
+}
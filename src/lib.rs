@@ -502,3 +502,6 @@
 //!
 //! This blog post was produced using [cargo-readme](https://docs.rs/cargo-readme) to ensure that
 //! all of the code actually works. The source code is [here](https://github.com/paulkernfeld/exploring-computation-graphs-in-rust).
+
+pub mod enum_graph;
+pub mod trait_graph;
@@ -0,0 +1,243 @@
+//! The trait-based computation graph from the post, along with conversions to and from the enum
+//! version so that the two can be compared on the same graphs.
+//!
+//! Only the node types from the post, plus `Scale`, are available here. Their derivatives each
+//! fit in a single new node, which is what `Node::derivative` requires.
+
+use std::collections::{HashMap, HashSet};
+use std::ops::Index;
+
+use enum_graph::{self, GraphError, Idx};
+
+pub trait Node: 'static {
+    /// The input must include values for all variables and for all children of this node.
+    fn get_value(&self, my_index: &Idx, values: &HashMap<Idx, f64>) -> f64;
+
+    fn derivative(
+        &self,
+        my_index: &Idx,
+        wrt: &HashSet<Idx>,
+        derivatives: &HashMap<Idx, Idx>,
+    ) -> Box<dyn Node>;
+
+    /// The equivalent node in the enum-based graph.
+    fn to_enum(&self) -> enum_graph::Node;
+}
+
+pub struct Constant(pub f64);
+
+impl Node for Constant {
+    fn get_value(&self, _my_index: &Idx, _values: &HashMap<Idx, f64>) -> f64 {
+        self.0
+    }
+
+    fn derivative(
+        &self,
+        _my_index: &Idx,
+        _wrt: &HashSet<Idx>,
+        _derivatives: &HashMap<Idx, Idx>,
+    ) -> Box<dyn Node> {
+        Box::from(Constant(0.0))
+    }
+
+    fn to_enum(&self) -> enum_graph::Node {
+        enum_graph::Node::Constant(self.0)
+    }
+}
+
+pub struct Variable;
+
+impl Node for Variable {
+    fn get_value(&self, my_index: &Idx, values: &HashMap<Idx, f64>) -> f64 {
+        values[my_index]
+    }
+
+    fn derivative(
+        &self,
+        my_index: &Idx,
+        wrt: &HashSet<Idx>,
+        _derivatives: &HashMap<Idx, Idx>,
+    ) -> Box<dyn Node> {
+        if wrt.contains(my_index) {
+            Box::from(Constant(1.0))
+        } else {
+            Box::from(Constant(0.0))
+        }
+    }
+
+    fn to_enum(&self) -> enum_graph::Node {
+        enum_graph::Node::Variable
+    }
+}
+
+pub struct Sum {
+    pub children: Vec<Idx>,
+}
+
+impl Node for Sum {
+    fn get_value(&self, _my_index: &Idx, values: &HashMap<Idx, f64>) -> f64 {
+        self.children.iter().map(|child| values[child]).sum()
+    }
+
+    fn derivative(
+        &self,
+        _my_index: &Idx,
+        _wrt: &HashSet<Idx>,
+        derivatives: &HashMap<Idx, Idx>,
+    ) -> Box<dyn Node> {
+        Box::from(Sum {
+            children: self
+                .children
+                .iter()
+                .map(|child| derivatives[child])
+                .collect(),
+        })
+    }
+
+    fn to_enum(&self) -> enum_graph::Node {
+        enum_graph::Node::Sum {
+            children: self.children.clone(),
+        }
+    }
+}
+
+pub struct Scale {
+    pub child: Idx,
+    pub factor: f64,
+}
+
+impl Node for Scale {
+    fn get_value(&self, _my_index: &Idx, values: &HashMap<Idx, f64>) -> f64 {
+        self.factor * values[&self.child]
+    }
+
+    fn derivative(
+        &self,
+        _my_index: &Idx,
+        _wrt: &HashSet<Idx>,
+        derivatives: &HashMap<Idx, Idx>,
+    ) -> Box<dyn Node> {
+        Box::from(Scale {
+            child: derivatives[&self.child],
+            factor: self.factor,
+        })
+    }
+
+    fn to_enum(&self) -> enum_graph::Node {
+        enum_graph::Node::Scale {
+            child: self.child,
+            factor: self.factor,
+        }
+    }
+}
+
+/// Since Node does not implement Sized, we need to box it so we can put it into a Vec.
+#[derive(Default)]
+pub struct Graph {
+    nodes: Vec<Box<dyn Node>>,
+}
+
+impl Graph {
+    pub fn push_box(&mut self, box_node: Box<dyn Node>) -> Idx {
+        self.nodes.push(box_node);
+        Idx(self.nodes.len() - 1)
+    }
+
+    pub fn push<N: Node>(&mut self, node: N) -> Idx {
+        self.push_box(Box::from(node))
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn evaluate(&self, variable_to_value: HashMap<Idx, f64>) -> HashMap<Idx, f64> {
+        let mut result = variable_to_value;
+        for (i, node) in self.nodes.iter().enumerate() {
+            let value = node.get_value(&Idx(i), &result);
+            result.insert(Idx(i), value);
+        }
+        result
+    }
+
+    pub fn derivative(&mut self, of: Idx, wrt: HashSet<Idx>) -> Idx {
+        let mut derivatives: HashMap<Idx, Idx> = HashMap::new();
+        for old_index in 0..self.nodes.len() {
+            let old_index = Idx(old_index);
+            let new_node = self[old_index].derivative(&old_index, &wrt, &derivatives);
+            let new_index = self.push_box(new_node);
+            derivatives.insert(old_index, new_index);
+        }
+        derivatives[&of]
+    }
+
+    /// Copies an enum-based graph node by node, so every `Idx` means the same thing in both
+    /// graphs. This fails on the first node that has no trait-based equivalent.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use exploring_computation_graphs_in_rust::enum_graph::{self, GraphError, Node};
+    /// use exploring_computation_graphs_in_rust::trait_graph;
+    ///
+    /// // 3 * (1 + b)
+    /// let mut g = enum_graph::Graph::default();
+    /// let a = g.push(Node::Constant(1.0));
+    /// let b = g.push(Node::Variable);
+    /// let c = g.push(a + b);
+    /// let d = g.push(c * 3.0);
+    ///
+    /// let converted = trait_graph::Graph::from_enum(&g).unwrap();
+    /// let values: HashMap<_, _> = vec![(b, 2.0)].into_iter().collect();
+    /// assert_eq!(g.evaluate(values.clone())[&d], converted.evaluate(values.clone())[&d]);
+    /// assert_eq!(9.0, converted.evaluate(values)[&d]);
+    ///
+    /// // And back again
+    /// assert_eq!(g, converted.to_enum());
+    ///
+    /// // Products can't be converted
+    /// let product = g.push(b * c);
+    /// assert_eq!(
+    ///     Err(GraphError::Unconvertible(product)),
+    ///     trait_graph::Graph::from_enum(&g).map(|_| ())
+    /// );
+    /// ```
+    pub fn from_enum(graph: &enum_graph::Graph) -> Result<Graph, GraphError> {
+        let mut result = Graph::default();
+
+        for i in 0..graph.len() {
+            let node: Box<dyn Node> = match graph[Idx(i)] {
+                enum_graph::Node::Constant(value) => Box::from(Constant(value)),
+                enum_graph::Node::Variable => Box::from(Variable),
+                enum_graph::Node::Sum { ref children } => Box::from(Sum {
+                    children: children.clone(),
+                }),
+                enum_graph::Node::Scale { child, factor } => Box::from(Scale { child, factor }),
+                _ => return Err(GraphError::Unconvertible(Idx(i))),
+            };
+            result.push_box(node);
+        }
+
+        Ok(result)
+    }
+
+    /// Converts back to an enum-based graph, which can represent every trait-based node.
+    pub fn to_enum(&self) -> enum_graph::Graph {
+        let mut result = enum_graph::Graph::default();
+        for node in &self.nodes {
+            result.push(node.to_enum());
+        }
+        result
+    }
+}
+
+impl Index<Idx> for Graph {
+    type Output = dyn Node;
+
+    fn index(&self, index: Idx) -> &dyn Node {
+        &*self.nodes[index.0]
+    }
+}